@@ -0,0 +1,86 @@
+summary-total-items = Total items: { $count }
+summary-empty-pages = Pages without products: { $count }
+summary-empty-retries = Fetched { $count } empty pages that declared products again, { $recovered } listed products on a later try
+summary-selector-fallback = { $field } was read { $count } times through the fallback { $selector }
+summary-new-category = New category: { $link }
+
+timings-stages = Time spent: fetch { $fetch }, parse { $parse }, sink { $sink } over { $pages } pages
+timings-slow-page = Slow page: { $url } (fetch { $fetch }, parse { $parse })
+
+dedupe-header = { $clusters } near-duplicate clusters in { $items } items
+
+report-column-discount = Discount
+report-column-name = Product
+report-column-type = Type
+report-column-price = Price
+report-column-promo = Sale price
+report-plain-group = Discount: { $discount }. { $count } products.
+report-plain-item = Product { $position } of { $count }: { $name }. Type: { $type }. Original price: { $price } pesos. Sale price: { $promo } pesos. Link: { $link }.
+report-no-discount = no discount
+
+diff-summary = { $added } new products, { $removed } removed, { $changed } price changes
+diff-price-change = Price change: { $name } from { $old } to { $new }
+
+coverage-line = { $field }: { $chart } { $latest }% in the latest run
+
+watch-item = { $name }: { $price } ({ $status ->
+        [sold-out] sold out
+       *[available] in stock
+    }) { $link }
+
+maintain-task = { $task }: { $status ->
+        [done] done
+        [skipped] skipped
+       *[failed] FAILED
+    } ({ $detail })
+maintain-backed-up = copied the store to { $path }, deleted { $pruned } old backups
+maintain-migrated = schema is current, was version { $previous }
+maintain-deduped = merged { $count } duplicate products
+maintain-rotated = deleted { $count } old versions
+maintain-no-store = no --store configured
+maintain-no-backup-dir = no --backup-dir given
+maintain-no-cache = the scraper keeps no response cache
+maintain-no-archive = no --archive directory given
+
+review-item = #{ $id } { $name }: { $previous } → { $price } (×{ $factor }) { $link }
+review-empty = No prices are waiting for review
+review-accepted = { $count ->
+        [one] Recorded one price in the history
+       *[other] Recorded { $count } prices in the history
+    }
+review-rejected = { $count ->
+        [one] Dropped one price
+       *[other] Dropped { $count } prices
+    }
+
+telegram-header = { $count ->
+        [one] One price drop
+       *[other] { $count } price drops
+    }
+telegram-drop = { $name }: { $old } → { $new } (-{ $percent }%) { $link }
+telegram-categories-header = { $count ->
+        [one] One new category
+       *[other] { $count } new categories
+    }
+telegram-new-category = New category: { $link }
+
+discord-summary = { $count ->
+        [one] One new deal since the last run
+       *[other] { $count } new deals since the last run
+    }
+discord-field-price = Price
+discord-field-discount = Discount
+discord-new-category = New category: { $link }
+
+demo-step-scrape = == Scraping the recorded site ==
+demo-step-diff = == Changes since the previous run ==
+demo-step-report = == Report ==
+demo-step-telegram = == Telegram alert that would be sent ==
+demo-step-discord = == Discord message that would be posted ==
+demo-outputs = The output files are in { $dir }. Run without "demo" to scrape the live site.
+
+email-subject = { $count ->
+        [one] Today's best discount
+       *[other] Today's { $count } best discounts
+    }
+email-new-categories = New categories
@@ -0,0 +1,86 @@
+summary-total-items = Productos encontrados: { $count }
+summary-empty-pages = Páginas sin productos: { $count }
+summary-empty-retries = Se volvieron a descargar { $count } páginas vacías que declaraban productos; { $recovered } los mostraron en otro intento
+summary-selector-fallback = { $field } se leyó { $count } veces con el respaldo { $selector }
+summary-new-category = Categoría nueva: { $link }
+
+timings-stages = Tiempo invertido: descarga { $fetch }, análisis { $parse }, escritura { $sink } en { $pages } páginas
+timings-slow-page = Página lenta: { $url } (descarga { $fetch }, análisis { $parse })
+
+dedupe-header = { $clusters } grupos de posibles duplicados en { $items } productos
+
+report-column-discount = Descuento
+report-column-name = Producto
+report-column-type = Tipo
+report-column-price = Precio
+report-column-promo = Precio con descuento
+report-plain-group = Descuento: { $discount }. { $count } productos.
+report-plain-item = Producto { $position } de { $count }: { $name }. Tipo: { $type }. Precio original: { $price } pesos. Precio con descuento: { $promo } pesos. Enlace: { $link }.
+report-no-discount = sin descuento
+
+diff-summary = { $added } productos nuevos, { $removed } retirados, { $changed } cambios de precio
+diff-price-change = Cambio de precio: { $name } de { $old } a { $new }
+
+coverage-line = { $field }: { $chart } { $latest }% en la última ejecución
+
+watch-item = { $name }: { $price } ({ $status ->
+        [sold-out] agotado
+       *[available] disponible
+    }) { $link }
+
+maintain-task = { $task }: { $status ->
+        [done] listo
+        [skipped] omitido
+       *[failed] FALLÓ
+    } ({ $detail })
+maintain-backed-up = se copió el almacén a { $path }, se borraron { $pruned } respaldos anteriores
+maintain-migrated = el esquema está al día, era la versión { $previous }
+maintain-deduped = se fusionaron { $count } productos duplicados
+maintain-rotated = se borraron { $count } versiones anteriores
+maintain-no-store = no se configuró --store
+maintain-no-backup-dir = no se indicó --backup-dir
+maintain-no-cache = el scraper no guarda caché de respuestas
+maintain-no-archive = no se indicó un directorio --archive
+
+review-item = #{ $id } { $name }: { $previous } → { $price } (×{ $factor }) { $link }
+review-empty = No hay precios pendientes de revisión
+review-accepted = { $count ->
+        [one] Se registró un precio en el historial
+       *[other] Se registraron { $count } precios en el historial
+    }
+review-rejected = { $count ->
+        [one] Se descartó un precio
+       *[other] Se descartaron { $count } precios
+    }
+
+telegram-header = { $count ->
+        [one] Bajó un precio
+       *[other] Bajaron { $count } precios
+    }
+telegram-drop = { $name }: { $old } → { $new } (-{ $percent }%) { $link }
+telegram-categories-header = { $count ->
+        [one] Una categoría nueva
+       *[other] { $count } categorías nuevas
+    }
+telegram-new-category = Categoría nueva: { $link }
+
+discord-summary = { $count ->
+        [one] Una oferta nueva desde la última ejecución
+       *[other] { $count } ofertas nuevas desde la última ejecución
+    }
+discord-field-price = Precio
+discord-field-discount = Descuento
+discord-new-category = Categoría nueva: { $link }
+
+demo-step-scrape = == Extrayendo el sitio grabado ==
+demo-step-diff = == Cambios desde la ejecución anterior ==
+demo-step-report = == Reporte ==
+demo-step-telegram = == Alerta de Telegram que se enviaría ==
+demo-step-discord = == Mensaje de Discord que se publicaría ==
+demo-outputs = Los archivos de salida están en { $dir }. Ejecuta sin "demo" para extraer el sitio real.
+
+email-subject = { $count ->
+        [one] El mejor descuento de hoy
+       *[other] Los { $count } mejores descuentos de hoy
+    }
+email-new-categories = Categorías nuevas
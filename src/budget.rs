@@ -0,0 +1,69 @@
+use std::cmp::Ordering;
+use std::time::{Duration, Instant};
+
+use crate::filters::discount_percent;
+use crate::BnBItem;
+
+/// Time left for a run under `--max-runtime`. Category listings always run
+/// to the end; the `--deep` stage only gets what they leave over.
+#[derive(Debug, Clone, Copy)]
+pub struct RunBudget {
+    deadline: Option<Instant>,
+}
+
+impl RunBudget {
+    /// A budget starting now; `None` never runs out.
+    pub fn new(max_runtime: Option<Duration>) -> Self {
+        RunBudget {
+            deadline: max_runtime.map(|runtime| Instant::now() + runtime),
+        }
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    pub fn exhausted(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+/// Order in which the `--deep` stage visits items, so a tight budget is
+/// spent on the best deals: biggest discount first, then promo items, then
+/// the most expensive ones.
+pub fn by_detail_priority(a: &BnBItem, b: &BnBItem) -> Ordering {
+    let discount = |item: &BnBItem| discount_percent(item).unwrap_or(0.0);
+    discount(b)
+        .total_cmp(&discount(a))
+        .then_with(|| (b.price_promo > 0.0).cmp(&(a.price_promo > 0.0)))
+        .then_with(|| b.price.total_cmp(&a.price))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefers_the_biggest_deals() {
+        let item = |name: &str, price: f32, price_promo: f32| BnBItem {
+            name: name.to_string(),
+            price,
+            price_promo,
+            ..BnBItem::default()
+        };
+        let mut items = [
+            item("Sin descuento", 300.0, 0.0),
+            item("Diez", 100.0, 90.0),
+            item("Mitad", 100.0, 50.0),
+            item("Barato", 50.0, 0.0),
+        ];
+
+        items.sort_by(by_detail_priority);
+        let names: Vec<&str> = items.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(names, vec!["Mitad", "Diez", "Sin descuento", "Barato"]);
+
+        assert!(RunBudget::new(Some(Duration::from_secs(0))).exhausted());
+        assert!(!RunBudget::new(None).exhausted());
+    }
+}
@@ -0,0 +1,131 @@
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use color_eyre::Report;
+use tracing::info;
+
+use crate::identity::Matcher;
+use crate::BnBItem;
+
+static NEXT_BUFFER: AtomicUsize = AtomicUsize::new(0);
+
+/// Collects scraped items, keeping at most `limit` of them in memory and
+/// spilling the rest to NDJSON segments in a temporary directory. Segments
+/// are read back and de-duplicated once the crawl is over.
+pub struct ItemBuffer {
+    limit: Option<usize>,
+    matcher: Matcher,
+    in_memory: Vec<BnBItem>,
+    spill_dir: PathBuf,
+    segments: Vec<PathBuf>,
+}
+
+impl ItemBuffer {
+    pub fn new(limit: Option<usize>, matcher: Matcher) -> Self {
+        let spill_dir = std::env::temp_dir().join(format!(
+            "bnbscraper-spill-{}-{}",
+            std::process::id(),
+            NEXT_BUFFER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        ItemBuffer {
+            limit,
+            matcher,
+            in_memory: Vec::new(),
+            spill_dir,
+            segments: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, item: BnBItem) -> Result<(), Report> {
+        if !self.matcher.contains(&self.in_memory, &item) {
+            self.in_memory.push(item);
+        }
+
+        match self.limit {
+            Some(limit) if self.in_memory.len() >= limit => self.spill(),
+            _ => Ok(()),
+        }
+    }
+
+    fn spill(&mut self) -> Result<(), Report> {
+        fs::create_dir_all(&self.spill_dir)?;
+        let segment = self
+            .spill_dir
+            .join(format!("segment-{}.ndjson", self.segments.len()));
+
+        let mut writer = BufWriter::new(File::create(&segment)?);
+        for item in self.in_memory.drain(..) {
+            serde_json::to_writer(&mut writer, &item)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+
+        info!("Spilled items to {}", segment.display());
+        self.segments.push(segment);
+        Ok(())
+    }
+
+    /// Merges every spilled segment with what is still in memory, keeping
+    /// the order in which items were pushed.
+    pub fn into_items(mut self) -> Result<Vec<BnBItem>, Report> {
+        let mut items = Vec::new();
+
+        for segment in &self.segments {
+            for line in BufReader::new(File::open(segment)?).lines() {
+                let item: BnBItem = serde_json::from_str(&line?)?;
+                if !self.matcher.contains(&items, &item) {
+                    items.push(item);
+                }
+            }
+        }
+        for item in std::mem::take(&mut self.in_memory) {
+            if !self.matcher.contains(&items, &item) {
+                items.push(item);
+            }
+        }
+
+        Ok(items)
+    }
+}
+
+impl Drop for ItemBuffer {
+    fn drop(&mut self) {
+        if !self.segments.is_empty() {
+            let _ = fs::remove_dir_all(&self.spill_dir);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(name: &str) -> BnBItem {
+        BnBItem {
+            name: name.to_string(),
+            item_type: "Vela de 3 mechas".to_string(),
+            ..BnBItem::default()
+        }
+    }
+
+    #[test]
+    fn merges_spilled_segments_without_duplicates() {
+        let mut buffer = ItemBuffer::new(Some(2), Matcher::default());
+        for name in &["Mahogany", "Vanilla", "Mahogany", "Champagne"] {
+            buffer.push(item(name)).unwrap();
+        }
+        let spill_dir = buffer.spill_dir.clone();
+
+        let names: Vec<String> = buffer
+            .into_items()
+            .unwrap()
+            .into_iter()
+            .map(|item| item.name)
+            .collect();
+        assert_eq!(names, vec!["Mahogany", "Vanilla", "Champagne"]);
+        assert!(!spill_dir.exists());
+    }
+}
@@ -0,0 +1,290 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::WrapErr;
+use color_eyre::Report;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::{BnBItem, ProductDetails};
+
+/// What one site's crawl got through before it stopped.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Progress {
+    /// Category links scraped to the end, with how many products each
+    /// listed; None for the "no products" template.
+    pub categories: BTreeMap<String, Option<usize>>,
+    /// Items kept from those categories, not yet de-duplicated. Only filled
+    /// when resuming; the crawl takes them and records new ones to disk.
+    pub items: Vec<BnBItem>,
+    /// Product pages read in `--deep` mode, by item link, when resuming.
+    pub details: BTreeMap<String, PageDetails>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PageDetails {
+    pub details: ProductDetails,
+    pub available: bool,
+}
+
+/// One line of the checkpoint file. Lines are only ever appended, so a
+/// checkpoint costs the size of each category once rather than a rewrite of
+/// everything scraped so far.
+#[derive(Serialize, Deserialize, Debug)]
+struct Line {
+    /// The root URL of the site the line belongs to, so a multi-site run
+    /// resumes every site where it stopped.
+    site: String,
+    #[serde(flatten)]
+    entry: Entry,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+enum Entry {
+    /// A crawl of the site started over; earlier lines no longer count.
+    Restart,
+    Category {
+        link: String,
+        listed: Option<usize>,
+        items: Vec<BnBItem>,
+    },
+    Details {
+        link: String,
+        #[serde(flatten)]
+        page: PageDetails,
+    },
+}
+
+/// Appends a crawl's progress to `--checkpoint` as JSON lines, flushing
+/// every `--checkpoint-every` categories or product pages. Writing is
+/// best-effort: a failed write is logged and the crawl goes on.
+pub struct Checkpointer {
+    path: Option<PathBuf>,
+    key: String,
+    every: usize,
+    unsaved: usize,
+    writer: Option<BufWriter<File>>,
+    pub progress: Progress,
+}
+
+impl Checkpointer {
+    /// Picks up this site's saved progress when `--resume` is set, and
+    /// otherwise starts its progress over.
+    pub fn new(config: &Config) -> Self {
+        let key = config.root_url.to_string();
+        let progress = match &config.checkpoint {
+            Some(path) if config.resume && path.exists() => match load(path, &key) {
+                Ok(progress) => progress,
+                Err(err) => {
+                    warn!("Starting over, the checkpoint can't be read: {:#}", err);
+                    Progress::default()
+                }
+            },
+            _ => Progress::default(),
+        };
+        if !progress.categories.is_empty() {
+            info!(
+                "Resuming after {} categories, {} items and {} product pages",
+                progress.categories.len(),
+                progress.items.len(),
+                progress.details.len()
+            );
+        }
+        let mut checkpointer = Checkpointer {
+            path: config.checkpoint.clone(),
+            key,
+            every: config.checkpoint_every.max(1),
+            unsaved: 0,
+            writer: None,
+            progress,
+        };
+        if !config.resume {
+            checkpointer.append(Entry::Restart);
+        }
+        checkpointer
+    }
+
+    /// Keeps nothing and writes nothing, for runs that aren't crawls.
+    pub fn disabled() -> Self {
+        Checkpointer {
+            path: None,
+            key: String::new(),
+            every: 1,
+            unsaved: 0,
+            writer: None,
+            progress: Progress::default(),
+        }
+    }
+
+    pub fn is_done(&self, category: &str) -> bool {
+        self.progress.categories.contains_key(category)
+    }
+
+    pub fn record_category(&mut self, link: &str, listed: Option<usize>, kept: &[BnBItem]) {
+        self.progress.categories.insert(link.to_string(), listed);
+        self.append(Entry::Category {
+            link: link.to_string(),
+            listed,
+            items: kept.to_vec(),
+        });
+    }
+
+    pub fn record_details(&mut self, link: &str, details: &ProductDetails, available: bool) {
+        self.append(Entry::Details {
+            link: link.to_string(),
+            page: PageDetails {
+                details: details.clone(),
+                available,
+            },
+        });
+    }
+
+    fn append(&mut self, entry: Entry) {
+        if self.path.is_none() {
+            return;
+        }
+        let line = Line {
+            site: self.key.clone(),
+            entry,
+        };
+        if let Err(err) = self.write_line(&line) {
+            warn!("Could not write the checkpoint {:?}: {:#}", self.path, err);
+            return;
+        }
+        self.unsaved += 1;
+        if self.unsaved >= self.every {
+            self.flush();
+        }
+    }
+
+    fn write_line(&mut self, line: &Line) -> Result<(), Report> {
+        let writer = match (&mut self.writer, &self.path) {
+            (Some(writer), _) => writer,
+            (None, Some(path)) => {
+                let file = OpenOptions::new().create(true).append(true).open(path)?;
+                self.writer.insert(BufWriter::new(file))
+            }
+            (None, None) => return Ok(()),
+        };
+        serde_json::to_writer(&mut *writer, line)?;
+        writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// Writes the progress not saved yet.
+    pub fn flush(&mut self) {
+        if let (Some(path), Some(writer)) = (&self.path, &mut self.writer) {
+            match writer.flush() {
+                Ok(()) => self.unsaved = 0,
+                Err(err) => warn!("Could not write the checkpoint {:?}: {:#}", path, err),
+            }
+        }
+    }
+}
+
+/// Replays the lines of `key`'s site since its last restart. A last line
+/// cut short by a crash is skipped.
+fn load(path: &Path, key: &str) -> Result<Progress, Report> {
+    let file = File::open(path).wrap_err_with(|| format!("Opening {:?}", path))?;
+    let mut progress = Progress::default();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line: Line = match serde_json::from_str(&line?) {
+            Ok(line) => line,
+            Err(err) => {
+                warn!("Skipping line {} of {:?}: {}", number + 1, path, err);
+                continue;
+            }
+        };
+        if line.site != key {
+            continue;
+        }
+        match line.entry {
+            Entry::Restart => progress = Progress::default(),
+            Entry::Category {
+                link,
+                listed,
+                items,
+            } => {
+                progress.categories.insert(link, listed);
+                progress.items.extend(items);
+            }
+            Entry::Details { link, page } => {
+                progress.details.insert(link, page);
+            }
+        }
+    }
+    Ok(progress)
+}
+
+/// Deletes the checkpoint once a run has saved its output.
+pub fn clear(config: &Config) -> Result<(), Report> {
+    match &config.checkpoint {
+        Some(path) if path.exists() => Ok(fs::remove_file(path)?),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Url;
+
+    use super::*;
+
+    #[test]
+    fn resumes_the_progress_of_its_own_site() {
+        let path =
+            std::env::temp_dir().join(format!("bnbscraper-checkpoint-{}.json", std::process::id()));
+        let config = Config {
+            checkpoint: Some(path.clone()),
+            checkpoint_every: 2,
+            ..Config::default()
+        };
+        let resume = Config {
+            resume: true,
+            ..config.clone()
+        };
+        let other_site = Config {
+            root_url: Url::parse("https://www.bathandbodyworks.com").unwrap(),
+            ..config.clone()
+        };
+        let item = BnBItem {
+            name: "Uno".to_string(),
+            link: "/velas/uno".to_string(),
+            ..BnBItem::default()
+        };
+        let lines = || fs::read_to_string(&path).unwrap().lines().count();
+
+        let mut checkpointer = Checkpointer::new(&config);
+        checkpointer.record_category("/velas", Some(1), std::slice::from_ref(&item));
+        let first = lines();
+        checkpointer.record_details("/velas/uno", &ProductDetails::default(), false);
+        let buffered = lines();
+        checkpointer.flush();
+        let saved = lines();
+        let mut other = Checkpointer::new(&other_site);
+        other.record_category("/candles", None, &[]);
+        other.flush();
+
+        let resumed = Checkpointer::new(&resume);
+        Checkpointer::new(&config).flush();
+        let restarted = Checkpointer::new(&resume);
+        let other_resumed = Checkpointer::new(&Config {
+            resume: true,
+            ..other_site.clone()
+        });
+        clear(&config).unwrap();
+
+        // The restart line and /velas make two, flushed together.
+        assert_eq!((first, buffered, saved), (2, 2, 3));
+        assert!(resumed.is_done("/velas") && !resumed.is_done("/candles"));
+        assert_eq!(resumed.progress.items, vec![item]);
+        assert!(!resumed.progress.details["/velas/uno"].available);
+        assert!(restarted.progress.categories.is_empty());
+        assert!(other_resumed.is_done("/candles"));
+        assert!(!path.exists());
+    }
+}
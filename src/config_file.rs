@@ -0,0 +1,446 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::parser::ValueSource;
+use clap::{ArgMatches, ValueEnum};
+use color_eyre::eyre::{eyre, WrapErr};
+use color_eyre::Report;
+use regex::Regex;
+use reqwest::Url;
+use serde::Deserialize;
+
+use std::collections::BTreeMap;
+
+use crate::config::Config;
+use crate::selector::{Selector, SelectorProfile};
+use crate::site::{Site, TaxSettings};
+
+const DEFAULT_CONFIG_FILE: &str = "bnbscraper.toml";
+
+/// Settings a `bnbscraper.toml` may hold; these fields are the only keys it
+/// accepts, and other flags are command-line only. Most keys are the long
+/// flag name with underscores, e.g. `max_concurrency = 4` for
+/// `--max-concurrency 4`. Repeatable flags take a list under a plural key:
+/// `sites`, `categories`, `seeds`, `item_types`, `follow_zones`,
+/// `denied_paths`, `empty_markers` and `match_strategies` for `--site`,
+/// `--category`, `--seed`, `--item-type`, `--follow-zone`, `--deny-path`,
+/// `--empty-marker` and `--match-strategy`, and `webhook_headers` for
+/// `--webhook-header`. `sites` is ignored when `--root-url` is given.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    pub root_url: Option<String>,
+    pub sites: Option<Vec<Site>>,
+    pub output: Option<PathBuf>,
+    pub format: Option<String>,
+    pub store: Option<String>,
+    pub politeness_report: Option<PathBuf>,
+    pub lang: Option<String>,
+
+    pub polite: Option<bool>,
+    pub aggressive: Option<bool>,
+    pub confirm_aggressive: Option<bool>,
+    pub max_concurrency: Option<usize>,
+    pub min_delay_ms: Option<u64>,
+    pub requests_per_second: Option<f64>,
+    pub retries: Option<u32>,
+    pub backoff_ms: Option<u64>,
+    pub empty_retries: Option<u32>,
+    pub empty_retry_delay_ms: Option<u64>,
+    pub max_runtime: Option<u64>,
+    pub provenance: Option<bool>,
+    pub review_factor: Option<f32>,
+    pub checkpoint: Option<PathBuf>,
+    pub checkpoint_every: Option<usize>,
+    pub max_pages: Option<usize>,
+
+    pub discovery: Option<String>,
+    pub follow_zones: Option<Vec<String>>,
+    pub denied_paths: Option<Vec<String>>,
+    pub empty_markers: Option<Vec<String>>,
+    pub match_strategies: Option<Vec<String>>,
+    pub match_threshold: Option<f32>,
+    pub categories: Option<Vec<String>>,
+    pub seeds: Option<Vec<String>>,
+    pub seed_item_class: Option<String>,
+    pub prices_exclude_iva: Option<bool>,
+    pub iva_rate: Option<f32>,
+
+    pub min_discount: Option<f32>,
+    pub min_price: Option<f32>,
+    pub max_price: Option<f32>,
+    pub require_promo: Option<bool>,
+    pub in_stock_only: Option<bool>,
+    pub item_types: Option<Vec<String>>,
+    pub name_pattern: Option<String>,
+
+    pub telegram_bot_token: Option<String>,
+    pub telegram_chat_id: Option<String>,
+    pub alert_below: Option<f32>,
+    pub alert_drop_percent: Option<f32>,
+    pub discord_webhook: Option<String>,
+    pub webhook_url: Option<String>,
+    pub webhook_payload: Option<String>,
+    pub webhook_headers: Option<Vec<String>>,
+    pub webhook_retries: Option<u32>,
+    pub smtp_url: Option<String>,
+    pub email_from: Option<String>,
+    pub email_to: Option<Vec<String>>,
+    pub digest_limit: Option<usize>,
+
+    /// Replaces the selectors it names; the rest keep their defaults.
+    pub selectors: Option<SelectorProfile>,
+    /// Like `selectors`, for one site each, e.g. `[site_selectors.us]`.
+    pub site_selectors: Option<BTreeMap<Site, SelectorProfile>>,
+    /// Whether a site's prices include tax and the rate to add, e.g.
+    /// `[site_tax.us]` with `prices_exclude_tax = true` and `rate = 0.0825`.
+    pub site_tax: Option<BTreeMap<Site, TaxSettings>>,
+}
+
+impl ConfigFile {
+    pub fn load(path: &Path) -> Result<Self, Report> {
+        let text = fs::read_to_string(path).wrap_err_with(|| format!("Reading {:?}", path))?;
+        toml::from_str(&text).wrap_err_with(|| format!("Parsing {:?}", path))
+    }
+}
+
+fn parse_enum<T: ValueEnum>(value: &str, key: &str) -> Result<T, Report> {
+    T::from_str(value, true).map_err(|_| eyre!("Invalid {} {:?} in config file", key, value))
+}
+
+/// Copies each file value into `config` unless its flag was given on the
+/// command line.
+macro_rules! from_file {
+    ($config:ident, $file:ident, $matches:ident; $($field:ident),* $(,)?) => {
+        $(if let Some(value) = $file.$field {
+            if $matches.value_source(stringify!($field)) != Some(ValueSource::CommandLine) {
+                $config.$field = value.into();
+            }
+        })*
+    };
+}
+
+impl Config {
+    /// Layers the `--config` file (or `bnbscraper.toml`, when it exists)
+    /// under the command line, then validates the result.
+    pub fn merge_file(&mut self, matches: &ArgMatches) -> Result<(), Report> {
+        let path = match &self.config_file {
+            Some(path) => Some(path.clone()),
+            None => Some(PathBuf::from(DEFAULT_CONFIG_FILE)).filter(|path| path.exists()),
+        };
+        if let Some(path) = path {
+            self.apply_file(ConfigFile::load(&path)?, matches)?;
+        }
+        self.validate()
+    }
+
+    pub fn apply_file(&mut self, file: ConfigFile, matches: &ArgMatches) -> Result<(), Report> {
+        let from_cli = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+
+        if let (Some(url), false) = (&file.root_url, from_cli("root_url")) {
+            self.root_url = Url::parse(url).wrap_err("Invalid root_url in config file")?;
+        }
+        if let (Some(format), false) = (&file.format, from_cli("format")) {
+            self.format = format
+                .split(',')
+                .map(|format| parse_enum(format.trim(), "format"))
+                .collect::<Result<_, _>>()?;
+        }
+        if let (Some(lang), false) = (&file.lang, from_cli("lang")) {
+            self.lang = parse_enum(lang, "lang")?;
+        }
+        if let (Some(discovery), false) = (&file.discovery, from_cli("discovery")) {
+            self.discovery = parse_enum(discovery, "discovery")?;
+        }
+        if let (Some(zones), false) = (&file.follow_zones, from_cli("follow_zones")) {
+            self.follow_zones = zones
+                .iter()
+                .map(|zone| parse_enum(zone, "follow_zones"))
+                .collect::<Result<_, _>>()?;
+        }
+        // An explicit --root-url means one site, whatever the file lists.
+        if let (Some(sites), false, false) = (&file.sites, from_cli("sites"), from_cli("root_url"))
+        {
+            self.sites = sites.clone();
+        }
+        if let (Some(payload), false) = (&file.webhook_payload, from_cli("webhook_payload")) {
+            self.webhook_payload = parse_enum(payload, "webhook_payload")?;
+        }
+        if let (Some(pattern), false) = (&file.name_pattern, from_cli("name_pattern")) {
+            self.name_pattern =
+                Some(Regex::new(pattern).wrap_err("Invalid name_pattern in config file")?);
+        }
+
+        let file_sets_pace = (file.max_concurrency.is_some(), file.min_delay_ms.is_some());
+        let config = self;
+        from_file!(config, file, matches;
+            polite, aggressive, confirm_aggressive,
+            output, store, politeness_report, max_concurrency, min_delay_ms, requests_per_second, retries,
+            backoff_ms, empty_retries, empty_retry_delay_ms, max_runtime, provenance, review_factor, checkpoint, checkpoint_every, max_pages, categories, seeds, seed_item_class, prices_exclude_iva, iva_rate, min_discount,
+            min_price, max_price, require_promo, in_stock_only, item_types, denied_paths, empty_markers,
+            match_strategies, match_threshold,
+            telegram_bot_token, telegram_chat_id, alert_below, alert_drop_percent,
+            discord_webhook, webhook_url, webhook_headers, webhook_retries, smtp_url, email_from, email_to, digest_limit,
+        );
+        // The same presets as the flags' default_value_if, filling in what
+        // neither the command line nor the file set.
+        let preset = match (config.polite, config.aggressive) {
+            (true, false) => Some((2, 1000)),
+            (false, true) => Some((32, 0)),
+            _ => None,
+        };
+        if let Some((concurrency, delay_ms)) = preset {
+            if !from_cli("max_concurrency") && !file_sets_pace.0 {
+                config.max_concurrency = concurrency;
+            }
+            if !from_cli("min_delay_ms") && !file_sets_pace.1 {
+                config.min_delay_ms = delay_ms;
+            }
+        }
+        if let Some(selectors) = file.selectors {
+            config.selectors = selectors;
+        }
+        if let Some(site_selectors) = file.site_selectors {
+            config.site_selectors = site_selectors;
+        }
+        if let Some(site_tax) = file.site_tax {
+            config.site_taxes = site_tax;
+        }
+        Ok(())
+    }
+
+    /// Rejects settings that can't work together, whichever source they came from.
+    pub fn validate(&self) -> Result<(), Report> {
+        if self.polite && self.aggressive {
+            return Err(eyre!("polite and aggressive can't both be set"));
+        }
+        if self.aggressive && !self.confirm_aggressive {
+            return Err(eyre!(
+                "aggressive risks getting blocked, so it also needs confirm_aggressive"
+            ));
+        }
+        if self.max_concurrency == 0 || self.deep_concurrency == 0 {
+            return Err(eyre!("Concurrency must be at least 1"));
+        }
+        if let (Some(min), Some(max)) = (self.min_price, self.max_price) {
+            if min > max {
+                return Err(eyre!("min_price {} is above max_price {}", min, max));
+            }
+        }
+        if !(0.0..=1.0).contains(&self.iva_rate) {
+            return Err(eyre!("iva_rate {} must be between 0 and 1", self.iva_rate));
+        }
+        for (site, tax) in &self.site_taxes {
+            if !(0.0..=1.0).contains(&tax.rate) {
+                return Err(eyre!(
+                    "site_tax.{} rate {} must be between 0 and 1",
+                    site.code(),
+                    tax.rate
+                ));
+            }
+        }
+        if !(0.0..=1.0).contains(&self.match_threshold) {
+            return Err(eyre!(
+                "match_threshold {} must be between 0 and 1",
+                self.match_threshold
+            ));
+        }
+        if self.requests_per_second.is_some_and(|rate| rate <= 0.0) {
+            return Err(eyre!("requests_per_second must be positive"));
+        }
+        for (index, format) in self.format.iter().enumerate() {
+            if self.format[..index].contains(format) {
+                return Err(eyre!("format {} is listed twice", format.extension()));
+            }
+        }
+        if self.resume && self.checkpoint.is_none() {
+            return Err(eyre!("--resume needs a checkpoint to resume from"));
+        }
+        if self.review_factor.is_some_and(|factor| factor <= 1.0) {
+            return Err(eyre!("review_factor must be above 1"));
+        }
+        // Seed pages match tiles by `.<class>`, so it has to be a single class.
+        if self.seed_item_class.contains(char::is_whitespace)
+            || Selector::parse(&format!(".{}", self.seed_item_class)).is_err()
+        {
+            return Err(eyre!(
+                "seed_item_class {:?} is not a single CSS class",
+                self.seed_item_class
+            ));
+        }
+        let missing = [
+            (
+                "store",
+                self.store.is_some(),
+                cfg!(feature = "sqlite"),
+                "sqlite",
+            ),
+            (
+                "patch",
+                self.patch.is_some(),
+                cfg!(feature = "patch"),
+                "patch",
+            ),
+            (
+                "signing_key",
+                self.signing_key.is_some(),
+                cfg!(feature = "signing"),
+                "signing",
+            ),
+            (
+                "smtp_url",
+                self.smtp_url.is_some(),
+                cfg!(feature = "email"),
+                "email",
+            ),
+        ];
+        for (key, set, built, feature) in missing {
+            if set && !built {
+                return Err(eyre!(
+                    "{} needs bnbscraper built with the {} feature",
+                    key,
+                    feature
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{CommandFactory, FromArgMatches, Parser};
+
+    use super::*;
+    use crate::config::OutputFormat;
+    use crate::discovery::LinkZone;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        config: Config,
+    }
+
+    #[test]
+    fn command_line_flags_override_file_values() {
+        let file: ConfigFile = toml::from_str(
+            r#"
+            max_concurrency = 2
+            retries = 5
+            format = "csv, json"
+            item_types = ["Vela de 3 mechas"]
+            name_pattern = "(?i)pumpkin"
+            sites = ["mx", "us"]
+            follow_zones = ["nav"]
+            denied_paths = ["ayuda"]
+            empty_markers = ["Sin resultados"]
+            match_strategies = ["link", "fuzzy-name"]
+            match_threshold = 0.8
+
+            [selectors]
+            price = ".price-box .old-price"
+            "#,
+        )
+        .unwrap();
+        let matches = Cli::command().get_matches_from([
+            "bnbscraper",
+            "--retries",
+            "1",
+            "--root-url",
+            "https://staging.example",
+        ]);
+        let mut config = Cli::from_arg_matches(&matches).unwrap().config;
+
+        config.apply_file(file, &matches).unwrap();
+
+        assert!(config.sites.is_empty());
+        assert_eq!(config.follow_zones, vec![LinkZone::Nav]);
+        assert_eq!(config.denied_paths, vec!["ayuda"]);
+        assert_eq!(config.empty_markers, vec!["Sin resultados"]);
+        assert_eq!(config.match_strategies, vec!["link", "fuzzy-name"]);
+        assert_eq!(config.match_threshold, 0.8);
+
+        assert_eq!(config.max_concurrency, 2);
+        assert_eq!(config.retries, 1);
+        assert_eq!(config.format, vec![OutputFormat::Csv, OutputFormat::Json]);
+        assert_eq!(config.item_types, vec!["Vela de 3 mechas"]);
+        assert!(config.name_pattern.unwrap().is_match("Pumpkin Pecan"));
+        assert_eq!(config.selectors.price.to_string(), ".price-box .old-price");
+        assert_eq!(config.selectors.item, SelectorProfile::default().item);
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_contradictory_values() {
+        assert!(toml::from_str::<ConfigFile>("max_concurency = 2").is_err());
+
+        let config = Config {
+            min_price: Some(500.0),
+            max_price: Some(100.0),
+            ..Config::default()
+        };
+        assert!(config.validate().is_err());
+        assert!(Config::default().validate().is_ok());
+        for class in ["", "outlet tile", "tile>card"] {
+            let config = Config {
+                seed_item_class: class.to_string(),
+                ..Config::default()
+            };
+            assert!(config.validate().is_err(), "{:?}", class);
+        }
+    }
+
+    #[test]
+    fn applies_tax_settings_per_site() {
+        let file: ConfigFile = toml::from_str(
+            r#"
+            iva_rate = 0.08
+
+            [site_tax.us]
+            prices_exclude_tax = true
+            rate = 0.0825
+            "#,
+        )
+        .unwrap();
+        let matches = Cli::command().get_matches_from(["bnbscraper", "--prices-exclude-iva"]);
+        let mut config = Cli::from_arg_matches(&matches).unwrap().config;
+        config.apply_file(file, &matches).unwrap();
+
+        let tax = |site: Site| {
+            let config = config.for_site(site);
+            (config.prices_exclude_iva, config.iva_rate)
+        };
+        assert_eq!(tax(Site::Mx), (true, 0.08));
+        assert_eq!(tax(Site::Us), (true, 0.0825));
+        assert_eq!(tax(Site::Ca), (true, 0.05));
+    }
+
+    #[test]
+    fn applies_crawl_presets_from_the_file() {
+        let matches = Cli::command().get_matches_from(["bnbscraper", "--min-delay-ms", "250"]);
+        let mut config = Cli::from_arg_matches(&matches).unwrap().config;
+        config
+            .apply_file(toml::from_str("polite = true").unwrap(), &matches)
+            .unwrap();
+        assert_eq!((config.max_concurrency, config.min_delay_ms), (2, 250));
+
+        let matches = Cli::command().get_matches_from(["bnbscraper"]);
+        let mut config = Cli::from_arg_matches(&matches).unwrap().config;
+        config
+            .apply_file(toml::from_str("aggressive = true").unwrap(), &matches)
+            .unwrap();
+        assert_eq!(config.max_concurrency, 32);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn resumes_from_a_checkpoint_set_in_the_file() {
+        let file: ConfigFile = toml::from_str(r#"checkpoint = "crawl.json""#).unwrap();
+        let matches = Cli::command().get_matches_from(["bnbscraper", "--resume"]);
+        let mut config = Cli::from_arg_matches(&matches).unwrap().config;
+        assert!(config.validate().is_err());
+
+        config.apply_file(file, &matches).unwrap();
+
+        assert!(config.validate().is_ok());
+    }
+}
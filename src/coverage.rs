@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use color_eyre::Report;
+use fluent_bundle::FluentValue;
+
+use crate::i18n::Localizer;
+use crate::yields::YieldHistory;
+use crate::BnBItem;
+
+// Sparkline levels from 0% to 100%.
+const BARS: &[char] = &['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+type FieldCheck = (&'static str, fn(&BnBItem) -> bool);
+
+/// Percentage of items with each extracted field present. A field that
+/// slowly loses coverage usually means a selector stopped matching.
+pub fn field_coverage(items: &[BnBItem]) -> BTreeMap<String, f32> {
+    let fields: [FieldCheck; 5] = [
+        ("name", |item| !item.name.trim().is_empty()),
+        ("price", |item| item.price > 0.0),
+        ("promo", |item| item.price_promo > 0.0),
+        ("discount", |item| !item.discount.trim().is_empty()),
+        ("item_type", |item| !item.item_type.trim().is_empty()),
+    ];
+
+    fields
+        .iter()
+        .map(|(field, present)| {
+            let count = items.iter().filter(|item| present(item)).count();
+            let percent = if items.is_empty() {
+                0.0
+            } else {
+                count as f32 * 100.0 / items.len() as f32
+            };
+            (field.to_string(), percent)
+        })
+        .collect()
+}
+
+/// One character per run, taller for higher coverage.
+pub fn sparkline(percentages: &[f32]) -> String {
+    percentages
+        .iter()
+        .map(|percent| {
+            let level = (percent.clamp(0.0, 100.0) / 100.0 * (BARS.len() - 1) as f32).round();
+            BARS[level as usize]
+        })
+        .collect()
+}
+
+/// Prints each field's coverage trend from the yield history, oldest run first.
+pub fn report(history_file: &Path, localizer: &Localizer) -> Result<(), Report> {
+    let history = YieldHistory::load(history_file)?;
+    for (field, series) in history.coverage_series() {
+        let latest = series.last().copied().unwrap_or_default();
+        println!(
+            "{}",
+            localizer.text(
+                "coverage-line",
+                &[
+                    ("field", field.as_str().into()),
+                    ("chart", sparkline(&series).into()),
+                    ("latest", FluentValue::from(format!("{:.0}", latest))),
+                ],
+            )
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_field_presence_and_charts_it() {
+        let items = vec![
+            BnBItem {
+                name: "Uno".to_string(),
+                price: 100.0,
+                discount: "2x1".to_string(),
+                ..BnBItem::default()
+            },
+            BnBItem {
+                name: "Dos".to_string(),
+                ..BnBItem::default()
+            },
+        ];
+
+        let coverage = field_coverage(&items);
+        assert_eq!(coverage["name"], 100.0);
+        assert_eq!(coverage["price"], 50.0);
+        assert_eq!(coverage["promo"], 0.0);
+        assert_eq!(sparkline(&[100.0, 50.0, 0.0]), "█▅▁");
+    }
+}
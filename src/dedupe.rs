@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use color_eyre::Report;
+use fluent_bundle::FluentValue;
+
+use crate::i18n::Localizer;
+use crate::output::read_grouped_json;
+use crate::BnBItem;
+
+/// Lowercases, folds accents and collapses punctuation so that
+/// "Champagne Toast " and "champagne-toast" compare equal.
+pub fn normalize_name(name: &str) -> String {
+    let folded: String = name
+        .to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'á' | 'à' | 'ä' => 'a',
+            'é' | 'è' | 'ë' => 'e',
+            'í' | 'ì' | 'ï' => 'i',
+            'ó' | 'ò' | 'ö' => 'o',
+            'ú' | 'ù' | 'ü' => 'u',
+            'ñ' => 'n',
+            c if c.is_alphanumeric() => c,
+            _ => ' ',
+        })
+        .collect();
+
+    folded.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Groups items sharing a normalized name but differing in link or type.
+/// Only clusters with more than one distinct item are returned.
+pub fn clusters(items: &[BnBItem]) -> BTreeMap<String, Vec<&BnBItem>> {
+    let mut by_name: BTreeMap<String, Vec<&BnBItem>> = BTreeMap::new();
+    for item in items {
+        let cluster = by_name.entry(normalize_name(&item.name)).or_default();
+        let seen = cluster
+            .iter()
+            .any(|other| other.link == item.link && other.item_type == item.item_type);
+        if !seen {
+            cluster.push(item);
+        }
+    }
+
+    by_name.retain(|_, cluster| cluster.len() > 1);
+    by_name
+}
+
+pub fn report(json_file: &Path, localizer: &Localizer) -> Result<(), Report> {
+    let items: Vec<BnBItem> = read_grouped_json(json_file)?
+        .into_values()
+        .flatten()
+        .collect();
+
+    let clusters = clusters(&items);
+    println!(
+        "{}",
+        localizer.text(
+            "dedupe-header",
+            &[
+                ("clusters", FluentValue::from(clusters.len())),
+                ("items", FluentValue::from(items.len())),
+            ],
+        )
+    );
+    for (name, cluster) in clusters {
+        println!("\n{}", name);
+        for item in cluster {
+            println!("  {} | {} | {}", item.name, item.item_type, item.link);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(name: &str, item_type: &str, link: &str) -> BnBItem {
+        BnBItem {
+            name: name.to_string(),
+            item_type: item_type.to_string(),
+            link: link.to_string(),
+            ..BnBItem::default()
+        }
+    }
+
+    #[test]
+    fn normalizes_case_accents_and_punctuation() {
+        assert_eq!(normalize_name("  Champagne-Toast "), "champagne toast");
+        assert_eq!(normalize_name("Piña Colada"), "pina colada");
+    }
+
+    #[test]
+    fn clusters_items_with_the_same_normalized_name() {
+        let items = vec![
+            item("Champagne Toast", "Body Mist", "/p/champagne-toast-mist"),
+            item("champagne toast", "Body Mist", "/p/champagne-toast-mist-2"),
+            item("Champagne Toast", "Body Mist", "/p/champagne-toast-mist"),
+            item("Mahogany Teakwood", "Vela", "/p/mahogany"),
+        ];
+
+        let clusters = clusters(&items);
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters["champagne toast"].len(), 2);
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use std::fs;
+
+use async_trait::async_trait;
+use color_eyre::eyre::eyre;
+use color_eyre::Report;
+
+use crate::config::Config;
+use crate::diff;
+use crate::discord;
+use crate::fetch::Fetcher;
+use crate::i18n::Localizer;
+use crate::output::read_grouped_json;
+use crate::report::{self, ReportStyle};
+use crate::telegram;
+use crate::{BnBItem, Scraper};
+
+const HOMEPAGE: &str = r#"<nav><a href="/velas">Velas</a></nav>"#;
+const CATEGORY: &str = include_str!("../fixtures/category_listing.html");
+/// What the "previous run" found, so the diff has something to show.
+const PREVIOUS: &str = include_str!("../fixtures/demo_previous.json");
+
+/// Serves the bundled pages in place of the live site.
+struct RecordedSite(HashMap<String, String>);
+
+#[async_trait]
+impl Fetcher for RecordedSite {
+    async fn fetch(&self, url: &str) -> Result<String, Report> {
+        self.0
+            .get(url)
+            .cloned()
+            .ok_or_else(|| eyre!("{} is not part of the demo recording", url))
+    }
+}
+
+/// Runs the whole pipeline (scrape, save, diff, report and notifications)
+/// against bundled pages, without touching the network. Outputs go to a
+/// temporary directory and notifications are printed instead of sent.
+pub async fn run(localizer: &Localizer) -> Result<(), Report> {
+    let dir = std::env::temp_dir().join("bnbscraper-demo");
+    fs::create_dir_all(&dir)?;
+    let config = Config {
+        output: dir.join("data.json"),
+        retries: 0,
+        // The recording has no robots.txt to honor.
+        ignore_robots: true,
+        ..Config::default()
+    };
+
+    let root = config.root_url.clone();
+    let recording = RecordedSite(
+        vec![
+            (root.to_string(), HOMEPAGE.to_string()),
+            (root.join("/velas")?.to_string(), CATEGORY.to_string()),
+        ]
+        .into_iter()
+        .collect(),
+    );
+
+    println!("{}", localizer.text("demo-step-scrape", &[]));
+    let scraper = Scraper::new(recording, config.clone());
+    let mut run = scraper.scrape_all().await?;
+    run.save(&config)?;
+    for line in run.summary(localizer) {
+        println!("{}", line);
+    }
+
+    println!("\n{}", localizer.text("demo-step-diff", &[]));
+    let previous: Vec<BnBItem> = serde_json::from_str(PREVIOUS)?;
+    let changes = diff::diff(&previous, &run.items, &root);
+    for line in changes.summary(localizer) {
+        println!("{}", line);
+    }
+
+    println!("\n{}", localizer.text("demo-step-report", &[]));
+    let grouped = read_grouped_json(&config.require_json_output()?)?;
+    println!(
+        "{}",
+        report::render(&grouped, ReportStyle::Table, localizer)
+    );
+
+    println!("{}", localizer.text("demo-step-telegram", &[]));
+    for message in telegram::messages(&config, &changes, localizer) {
+        println!("{}\n", message);
+    }
+    println!("{}", localizer.text("demo-step-discord", &[]));
+    for payload in discord::payloads(&config, &changes, localizer) {
+        println!("{}", serde_json::to_string_pretty(&payload)?);
+    }
+
+    println!(
+        "\n{}",
+        localizer.text(
+            "demo-outputs",
+            &[("dir", dir.to_string_lossy().into_owned().into())]
+        )
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i18n::Lang;
+
+    #[tokio::test]
+    async fn runs_the_pipeline_offline() {
+        run(&Localizer::new(Lang::EnUs)).await.unwrap();
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use color_eyre::Report;
+use fluent_bundle::FluentValue;
+use reqwest::Url;
+use serde::Serialize;
+
+use crate::discovery::canonical_link;
+use crate::i18n::Localizer;
+use crate::output::read_grouped_json;
+use crate::site::root_for;
+#[cfg(feature = "sqlite")]
+use crate::store::SqliteStore;
+use crate::BnBItem;
+
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct PriceChange {
+    /// Code of the storefront, in `--site` runs.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub site: String,
+    pub name: String,
+    pub item_type: String,
+    pub link: String,
+    pub old_price: f32,
+    pub new_price: f32,
+    pub old_price_promo: f32,
+    pub new_price_promo: f32,
+}
+
+impl PriceChange {
+    /// What the product sold for before, promo or not.
+    pub fn old_effective(&self) -> f32 {
+        if self.old_price_promo > 0.0 {
+            self.old_price_promo
+        } else {
+            self.old_price
+        }
+    }
+
+    pub fn new_effective(&self) -> f32 {
+        if self.new_price_promo > 0.0 {
+            self.new_price_promo
+        } else {
+            self.new_price
+        }
+    }
+}
+
+/// What changed between two scrapes, keyed by site and canonical product link.
+#[derive(Serialize, Debug, Default)]
+pub struct RunDiff {
+    pub added: Vec<BnBItem>,
+    pub removed: Vec<BnBItem>,
+    pub price_changes: Vec<PriceChange>,
+    /// Category links discovered for the first time, from the yield history.
+    pub new_categories: Vec<String>,
+}
+
+impl RunDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.price_changes.is_empty()
+            && self.new_categories.is_empty()
+    }
+
+    pub fn summary(&self, localizer: &Localizer) -> Vec<String> {
+        let mut lines = vec![localizer.text(
+            "diff-summary",
+            &[
+                ("added", FluentValue::from(self.added.len())),
+                ("removed", FluentValue::from(self.removed.len())),
+                ("changed", FluentValue::from(self.price_changes.len())),
+            ],
+        )];
+        for change in &self.price_changes {
+            lines.push(localizer.text(
+                "diff-price-change",
+                &[
+                    ("name", change.name.as_str().into()),
+                    ("old", format!("{:.2}", change.old_effective()).into()),
+                    ("new", format!("{:.2}", change.new_effective()).into()),
+                ],
+            ));
+        }
+        lines
+    }
+}
+
+/// Items without a link cannot be matched across runs reliably, so they fall
+/// back to name and type. Links resolve against the item's own site, and
+/// the same path on two sites stays two products.
+fn key<'a>(root: &Url, item: &'a BnBItem) -> (&'a str, String) {
+    let product = if item.link.is_empty() {
+        format!("{}\u{1f}{}", item.name, item.item_type)
+    } else {
+        canonical_link(&root_for(&item.site, root), &item.link)
+    };
+    (item.site.as_str(), product)
+}
+
+pub fn diff(previous: &[BnBItem], current: &[BnBItem], root: &Url) -> RunDiff {
+    let before: BTreeMap<(&str, String), &BnBItem> = previous
+        .iter()
+        .map(|item| (key(root, item), item))
+        .collect();
+    let after: BTreeMap<(&str, String), &BnBItem> =
+        current.iter().map(|item| (key(root, item), item)).collect();
+
+    let mut diff = RunDiff::default();
+    for (key, item) in &after {
+        match before.get(key) {
+            None => diff.added.push((*item).clone()),
+            Some(old) if old.price != item.price || old.price_promo != item.price_promo => {
+                diff.price_changes.push(PriceChange {
+                    site: item.site.clone(),
+                    name: item.name.clone(),
+                    item_type: item.item_type.clone(),
+                    link: item.link.clone(),
+                    old_price: old.price,
+                    new_price: item.price,
+                    old_price_promo: old.price_promo,
+                    new_price_promo: item.price_promo,
+                })
+            }
+            Some(_) => {}
+        }
+    }
+    for (key, item) in &before {
+        if !after.contains_key(key) {
+            diff.removed.push((*item).clone());
+        }
+    }
+    diff
+}
+
+/// Loads the items of one side of a diff: a grouped JSON output file, or a
+/// `sqlite://` store, read `runs_back` runs before its latest one.
+pub fn load_items(source: &str, runs_back: usize) -> Result<Vec<BnBItem>, Report> {
+    if source.starts_with("sqlite://") {
+        load_store_items(source, runs_back)
+    } else {
+        Ok(read_grouped_json(Path::new(source))?
+            .into_values()
+            .flatten()
+            .collect())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn load_store_items(source: &str, runs_back: usize) -> Result<Vec<BnBItem>, Report> {
+    SqliteStore::open(source)?.run_items(runs_back)
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn load_store_items(_source: &str, _runs_back: usize) -> Result<Vec<BnBItem>, Report> {
+    Err(color_eyre::eyre::eyre!(
+        "Reading a sqlite:// store needs bnbscraper built with the sqlite feature"
+    ))
+}
+
+/// Prints the diff as JSON on stdout and the human summary on stderr, so the
+/// JSON can be piped on without the summary getting in the way.
+pub fn run(
+    previous: &str,
+    current: &str,
+    root: &Url,
+    localizer: &Localizer,
+) -> Result<RunDiff, Report> {
+    // With a single store on both sides, compare its last two runs.
+    let runs_back = if previous == current { 1 } else { 0 };
+    let diff = diff(
+        &load_items(previous, runs_back)?,
+        &load_items(current, 0)?,
+        root,
+    );
+
+    println!("{}", serde_json::to_string_pretty(&diff)?);
+    for line in diff.summary(localizer) {
+        eprintln!("{}", line);
+    }
+    Ok(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i18n::Lang;
+
+    fn item(link: &str, price_promo: f32) -> BnBItem {
+        BnBItem {
+            name: link.trim_start_matches('/').to_string(),
+            link: link.to_string(),
+            price: 650.0,
+            price_promo,
+            ..BnBItem::default()
+        }
+    }
+
+    #[test]
+    fn reports_added_removed_and_repriced_items() {
+        let root = Url::parse("https://www.bathandbodyworks.mx").unwrap();
+        let previous = vec![item("/uno", 455.0), item("/dos", 300.0)];
+        let current = vec![item("/uno?color=rojo", 400.0), item("/tres", 200.0)];
+
+        let diff = diff(&previous, &current, &root);
+
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].link, "/tres");
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0].link, "/dos");
+        assert_eq!(diff.price_changes.len(), 1);
+        assert_eq!(diff.price_changes[0].old_price_promo, 455.0);
+        assert_eq!(diff.price_changes[0].new_price_promo, 400.0);
+    }
+
+    #[test]
+    fn keeps_the_same_path_on_two_sites_apart() {
+        let root = Url::parse("https://www.bathandbodyworks.mx").unwrap();
+        let on = |site: &str, price_promo: f32| BnBItem {
+            site: site.to_string(),
+            ..item("/velas/uno", price_promo)
+        };
+
+        let diff = diff(&[on("mx", 455.0)], &[on("mx", 400.0), on("us", 0.0)], &root);
+
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].site, "us");
+        assert_eq!(diff.price_changes.len(), 1);
+        assert_eq!(diff.price_changes[0].site, "mx");
+    }
+
+    #[test]
+    fn summarizes_list_price_changes_without_a_promo() {
+        let root = Url::parse("https://www.bathandbodyworks.mx").unwrap();
+        let current = BnBItem {
+            price: 700.0,
+            ..item("/uno", 0.0)
+        };
+
+        let diff = diff(&[item("/uno", 0.0)], &[current], &root);
+
+        assert_eq!(
+            diff.summary(&Localizer::new(Lang::EnUs))[1],
+            "Price change: uno from 650.00 to 700.00"
+        );
+    }
+}
@@ -0,0 +1,263 @@
+use std::time::Duration;
+
+use color_eyre::eyre::eyre;
+use color_eyre::Report;
+use fluent_bundle::FluentValue;
+use reqwest::{header, Client, StatusCode, Url};
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::diff::RunDiff;
+use crate::discovery::canonical_link;
+use crate::filters::discount_percent;
+use crate::i18n::Localizer;
+use crate::site::root_for;
+
+// Discord takes at most 10 embeds per message.
+const EMBEDS_PER_MESSAGE: usize = 10;
+const MAX_ATTEMPTS: u32 = 3;
+// Discord's blurple, so the cards stand out from ordinary messages.
+const EMBED_COLOR: u32 = 0x5865f2;
+
+/// A product that is on sale now and wasn't in the previous run.
+#[derive(Debug, PartialEq)]
+pub struct NewDeal {
+    pub site: String,
+    pub name: String,
+    pub link: String,
+    pub price: f32,
+    pub price_promo: f32,
+    pub discount: String,
+}
+
+/// New products that come discounted, and known products whose promo price
+/// appeared or went down.
+pub fn new_deals(diff: &RunDiff) -> Vec<NewDeal> {
+    let mut deals = vec![];
+    for item in &diff.added {
+        if item.price_promo > 0.0 || !item.discount.trim().is_empty() {
+            deals.push(NewDeal {
+                site: item.site.clone(),
+                name: item.name.clone(),
+                link: item.link.clone(),
+                price: item.price,
+                price_promo: item.price_promo,
+                discount: match (item.discount.trim(), discount_percent(item)) {
+                    ("", Some(percent)) => format!("{:.0}%", percent),
+                    (label, _) => label.to_string(),
+                },
+            });
+        }
+    }
+    for change in &diff.price_changes {
+        let newly_discounted = change.new_price_promo > 0.0
+            && (change.old_price_promo <= 0.0 || change.new_price_promo < change.old_price_promo);
+        if newly_discounted && change.new_price_promo < change.new_price {
+            deals.push(NewDeal {
+                site: change.site.clone(),
+                name: change.name.clone(),
+                link: change.link.clone(),
+                price: change.new_price,
+                price_promo: change.new_price_promo,
+                discount: format!(
+                    "{:.0}%",
+                    (1.0 - change.new_price_promo / change.new_price) * 100.0
+                ),
+            });
+        }
+    }
+    deals
+}
+
+/// One rich embed card per deal, linking to the deal's own site or `root`.
+pub fn embed(deal: &NewDeal, root: &Url, localizer: &Localizer) -> Value {
+    let price = if deal.price_promo > 0.0 {
+        format!("~~{:.2}~~ {:.2}", deal.price, deal.price_promo)
+    } else {
+        format!("{:.2}", deal.price)
+    };
+    let discount = if deal.discount.is_empty() {
+        "-"
+    } else {
+        deal.discount.as_str()
+    };
+    json!({
+        "title": deal.name,
+        "url": canonical_link(&root_for(&deal.site, root), &deal.link),
+        "color": EMBED_COLOR,
+        "fields": [
+            {"name": localizer.text("discord-field-price", &[]), "value": price, "inline": true},
+            {"name": localizer.text("discord-field-discount", &[]), "value": discount, "inline": true},
+        ],
+    })
+}
+
+/// The webhook messages for the run's new deals and new categories, as they
+/// would be posted.
+pub fn payloads(config: &Config, diff: &RunDiff, localizer: &Localizer) -> Vec<Value> {
+    let deals = new_deals(diff);
+    if deals.is_empty() && diff.new_categories.is_empty() {
+        return vec![];
+    }
+
+    let mut lines = vec![];
+    if !deals.is_empty() {
+        lines.push(localizer.text(
+            "discord-summary",
+            &[("count", FluentValue::from(deals.len()))],
+        ));
+    }
+    for link in &diff.new_categories {
+        lines.push(localizer.text("discord-new-category", &[("link", link.as_str().into())]));
+    }
+    let content = lines.join("\n");
+    let embeds: Vec<Value> = deals
+        .iter()
+        .map(|deal| embed(deal, &config.root_url, localizer))
+        .collect();
+    if embeds.is_empty() {
+        return vec![json!({"content": content, "embeds": []})];
+    }
+    embeds
+        .chunks(EMBEDS_PER_MESSAGE)
+        .enumerate()
+        .map(|(index, chunk)| {
+            json!({
+                // Only the first message carries the summary lines.
+                "content": if index == 0 { content.as_str() } else { "" },
+                "embeds": chunk,
+            })
+        })
+        .collect()
+}
+
+/// Posts the run's new deals to `--discord-webhook` as embeds, ten per
+/// message, with its new categories in the summary. Sends nothing when the
+/// diff has neither.
+pub async fn notify(
+    client: &Client,
+    config: &Config,
+    diff: &RunDiff,
+    localizer: &Localizer,
+) -> Result<(), Report> {
+    let webhook = match &config.discord_webhook {
+        Some(webhook) if !diff.is_empty() => webhook,
+        _ => return Ok(()),
+    };
+    let payloads = payloads(config, diff, localizer);
+    for body in &payloads {
+        send(client, webhook, body).await?;
+    }
+    if !payloads.is_empty() {
+        info!("Posted {} messages of new deals to Discord", payloads.len());
+    }
+    Ok(())
+}
+
+/// Posts one message, waiting out Discord's `retry_after` when it answers
+/// 429 Too Many Requests.
+async fn send(client: &Client, webhook: &str, body: &Value) -> Result<(), Report> {
+    for _ in 0..MAX_ATTEMPTS {
+        let response = client
+            .post(webhook)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+            .await
+            // The webhook URL is its secret, so it is left out of errors.
+            .map_err(|err| eyre!("Posting to Discord failed: {}", err.without_url()))?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+
+        let answer: Value = serde_json::from_str(&response.text().await?).unwrap_or_default();
+        if status != StatusCode::TOO_MANY_REQUESTS {
+            return Err(eyre!(
+                "Discord rejected the message ({}): {}",
+                status,
+                answer["message"].as_str().unwrap_or_default()
+            ));
+        }
+        let retry_after = answer["retry_after"].as_f64().unwrap_or(1.0);
+        warn!("Discord rate limit hit, retrying in {:.1}s", retry_after);
+        tokio::time::sleep(Duration::from_secs_f64(retry_after)).await;
+    }
+    Err(eyre!("Discord kept rate limiting the message"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::PriceChange;
+    use crate::i18n::Lang;
+    use crate::BnBItem;
+
+    #[test]
+    fn embeds_new_and_newly_cheaper_deals() {
+        let diff = RunDiff {
+            added: vec![
+                BnBItem {
+                    name: "Nueva".to_string(),
+                    link: "/velas/nueva?src=nav".to_string(),
+                    price: 650.0,
+                    price_promo: 455.0,
+                    ..BnBItem::default()
+                },
+                BnBItem {
+                    name: "Sin oferta".to_string(),
+                    price: 200.0,
+                    ..BnBItem::default()
+                },
+            ],
+            price_changes: vec![
+                PriceChange {
+                    site: String::new(),
+                    name: "Rebajada".to_string(),
+                    item_type: String::new(),
+                    link: "/velas/rebajada".to_string(),
+                    old_price: 400.0,
+                    new_price: 400.0,
+                    old_price_promo: 0.0,
+                    new_price_promo: 200.0,
+                },
+                PriceChange {
+                    site: String::new(),
+                    name: "Encarecida".to_string(),
+                    item_type: String::new(),
+                    link: "/velas/encarecida".to_string(),
+                    old_price: 400.0,
+                    new_price: 400.0,
+                    old_price_promo: 200.0,
+                    new_price_promo: 300.0,
+                },
+            ],
+            ..RunDiff::default()
+        };
+
+        let deals = new_deals(&diff);
+        let names: Vec<&str> = deals.iter().map(|deal| deal.name.as_str()).collect();
+        assert_eq!(names, vec!["Nueva", "Rebajada"]);
+        assert_eq!(deals[1].discount, "50%");
+
+        let root = Url::parse("https://www.bathandbodyworks.mx").unwrap();
+        let card = embed(&deals[0], &root, &Localizer::new(Lang::EnUs));
+        assert_eq!(card["url"], "https://www.bathandbodyworks.mx/velas/nueva");
+        assert_eq!(card["fields"][0]["value"], "~~650.00~~ 455.00");
+        assert_eq!(card["fields"][1]["value"], "30%");
+
+        let categories = RunDiff {
+            new_categories: vec!["https://www.bathandbodyworks.mx/halloween".to_string()],
+            ..RunDiff::default()
+        };
+        let localizer = Localizer::new(Lang::EnUs);
+        let messages = payloads(&Config::default(), &categories, &localizer);
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0]["content"]
+            .as_str()
+            .unwrap()
+            .ends_with("https://www.bathandbodyworks.mx/halloween"));
+        assert!(payloads(&Config::default(), &RunDiff::default(), &localizer).is_empty());
+    }
+}
@@ -0,0 +1,536 @@
+use std::collections::BTreeSet;
+
+use clap::ValueEnum;
+use reqwest::Url;
+use select::document::{Document, Find};
+use select::node::Node;
+use select::predicate::Name;
+
+use crate::config::Config;
+
+/// Where on a page a link appears, judged from its ancestors.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum LinkZone {
+    Nav,
+    Footer,
+    ProductCard,
+    Content,
+}
+
+pub fn link_zone(node: Node) -> LinkZone {
+    let mut current = node.parent();
+
+    while let Some(ancestor) = current {
+        let name = ancestor.name().unwrap_or_default();
+        let class = ancestor.attr("class").unwrap_or_default();
+
+        if name == "footer" || class.contains("footer") {
+            return LinkZone::Footer;
+        }
+        if class.contains("product-item") {
+            return LinkZone::ProductCard;
+        }
+        if name == "nav" || name == "header" || class.contains("menu") || class.contains("nav") {
+            return LinkZone::Nav;
+        }
+        current = ancestor.parent();
+    }
+
+    LinkZone::Content
+}
+
+/// Where category links come from.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum DiscoveryMode {
+    /// Anchors on the landing page
+    Homepage,
+    /// The site's /sitemap.xml, following nested sitemap indexes. Products
+    /// it lists are read from their own pages; without a readable sitemap
+    /// the landing page is used instead
+    Sitemap,
+    /// Both, merged
+    Both,
+}
+
+impl DiscoveryMode {
+    pub fn uses_homepage(self) -> bool {
+        self != DiscoveryMode::Sitemap
+    }
+
+    pub fn uses_sitemap(self) -> bool {
+        self != DiscoveryMode::Homepage
+    }
+}
+
+/// Which landing-page links are allowed into the crawl.
+pub struct DiscoveryRules {
+    root: Url,
+    zones: Vec<LinkZone>,
+    denied_paths: Vec<String>,
+    categories: Vec<String>,
+}
+
+impl DiscoveryRules {
+    pub fn from_config(config: &Config) -> Self {
+        DiscoveryRules {
+            root: config.root_url.clone(),
+            zones: config.follow_zones.clone(),
+            denied_paths: config.denied_paths.clone(),
+            categories: config.categories.clone(),
+        }
+    }
+
+    /// Only links whose path mentions one of the requested categories are
+    /// crawled; with none requested, everything is.
+    fn is_wanted_category(&self, link: &str) -> bool {
+        if self.categories.is_empty() {
+            return true;
+        }
+
+        let link = link.to_lowercase();
+        self.categories
+            .iter()
+            .any(|category| link.contains(&category.to_lowercase()))
+    }
+
+    fn is_denied(&self, link: &str) -> bool {
+        let path = match Url::parse(link) {
+            Ok(url) => url.path().to_ascii_lowercase(),
+            Err(_) => return false,
+        };
+
+        // Whole segments, ignoring an extension like `.html`, so "cart"
+        // doesn't also deny "carteras".
+        path.split('/').any(|segment| {
+            let stem = segment.split('.').next().unwrap_or_default();
+            self.denied_paths
+                .iter()
+                .any(|denied| !stem.is_empty() && stem == denied.as_str())
+        })
+    }
+}
+
+/// Anchors seen on the landing page that never made it into the crawl.
+#[derive(Debug, Default, PartialEq)]
+pub struct LinkDiagnostics {
+    pub missing_href: usize,
+    pub empty_href: usize,
+    pub pseudo_links: usize,
+    pub off_site: usize,
+    pub unfollowed_zone: usize,
+    pub denied: usize,
+    pub other_category: usize,
+    pub robots_disallowed: usize,
+}
+
+impl LinkDiagnostics {
+    pub fn absorb(&mut self, other: &LinkDiagnostics) {
+        self.missing_href += other.missing_href;
+        self.empty_href += other.empty_href;
+        self.pseudo_links += other.pseudo_links;
+        self.off_site += other.off_site;
+        self.unfollowed_zone += other.unfollowed_zone;
+        self.denied += other.denied;
+        self.other_category += other.other_category;
+        self.robots_disallowed += other.robots_disallowed;
+    }
+}
+
+pub fn get_unique_links(
+    links: Find<Name<&str>>,
+    rules: &DiscoveryRules,
+    diagnostics: &mut LinkDiagnostics,
+) -> Vec<String> {
+    let mut uniq_links = BTreeSet::new();
+
+    for node in links {
+        if !rules.zones.contains(&link_zone(node)) {
+            diagnostics.unfollowed_zone += 1;
+            continue;
+        }
+
+        let href = match node.attr("href").map(str::trim) {
+            Some(href) => href,
+            None => {
+                diagnostics.missing_href += 1;
+                continue;
+            }
+        };
+
+        if href.is_empty() || href == "#" {
+            diagnostics.empty_href += 1;
+        } else if href.to_ascii_lowercase().starts_with("javascript:") {
+            diagnostics.pseudo_links += 1;
+        } else if let Some(link) = normalize_link(&rules.root, href) {
+            if rules.is_denied(&link) {
+                diagnostics.denied += 1;
+            } else if !rules.is_wanted_category(&link) {
+                diagnostics.other_category += 1;
+            } else {
+                uniq_links.insert(link);
+            }
+        } else {
+            diagnostics.off_site += 1;
+        }
+    }
+
+    uniq_links.into_iter().collect()
+}
+
+/// The `<loc>`s of one sitemap file.
+#[derive(Debug, Default, PartialEq)]
+pub struct SitemapLocations {
+    /// Sitemaps listed by a sitemap index.
+    pub sitemaps: Vec<String>,
+    /// Listing pages, crawled like landing-page links.
+    pub pages: Vec<String>,
+    /// Product pages, read with the product-page extractor.
+    pub products: Vec<String>,
+}
+
+/// Both storefront platforms only attach `<image:image>` to product entries,
+/// and the US and Canadian stores keep products under `/p/`.
+fn is_product_entry(entry: Node, loc: &str) -> bool {
+    entry.find(Name("image:image")).next().is_some() || loc_path(loc).starts_with("/p/")
+}
+
+fn loc_path(loc: &str) -> &str {
+    match loc.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("/", |slash| &rest[slash..]),
+        None => loc,
+    }
+}
+
+/// The `<loc>`s of a sitemap: nested sitemaps when it is a sitemap index,
+/// otherwise its pages, with product pages told apart from listings.
+pub fn sitemap_locations(xml: &str) -> SitemapLocations {
+    let document = Document::from(xml);
+    let text = |loc: Node| Some(loc.text().trim().to_string()).filter(|loc| !loc.is_empty());
+
+    let mut locations = SitemapLocations::default();
+    if document.find(Name("sitemapindex")).next().is_some() {
+        locations.sitemaps = document.find(Name("loc")).filter_map(text).collect();
+        return locations;
+    }
+    for entry in document.find(Name("url")) {
+        let loc = match entry.find(Name("loc")).next().and_then(text) {
+            Some(loc) => loc,
+            None => continue,
+        };
+        if is_product_entry(entry, &loc) {
+            locations.products.push(loc);
+        } else {
+            locations.pages.push(loc);
+        }
+    }
+    locations
+}
+
+/// Applies the same site, denied-path and category rules as landing-page
+/// anchors to URLs listed in a sitemap.
+pub fn filter_sitemap_links(
+    urls: Vec<String>,
+    rules: &DiscoveryRules,
+    diagnostics: &mut LinkDiagnostics,
+) -> Vec<String> {
+    let mut uniq_links = BTreeSet::new();
+    for url in urls {
+        match normalize_link(&rules.root, &url) {
+            Some(link) if rules.is_denied(&link) => diagnostics.denied += 1,
+            Some(link) if !rules.is_wanted_category(&link) => diagnostics.other_category += 1,
+            Some(link) => {
+                uniq_links.insert(link);
+            }
+            None => diagnostics.off_site += 1,
+        }
+    }
+    uniq_links.into_iter().collect()
+}
+
+/// Resolves an href against the site root, keeping only http(s) links on the
+/// same host. Fragments are dropped so `/velas#top` and `/velas` collapse.
+pub fn normalize_link(root: &Url, href: &str) -> Option<String> {
+    let mut url = root.join(href).ok()?;
+
+    if !matches!(url.scheme(), "http" | "https") || url.host_str() != root.host_str() {
+        return None;
+    }
+    url.set_fragment(None);
+
+    Some(url.into())
+}
+
+/// Absolute link without query or fragment, so the same product page always
+/// maps to the same row.
+pub fn canonical_link(root: &Url, link: &str) -> String {
+    let absolute = normalize_link(root, link).unwrap_or_else(|| link.to_string());
+    match Url::parse(&absolute) {
+        Ok(mut url) => {
+            url.set_query(None);
+            let path = url.path().trim_end_matches('/').to_string();
+            url.set_path(&path);
+            url.into()
+        }
+        Err(_) => absolute,
+    }
+}
+
+fn page_number(url: &Url) -> Option<usize> {
+    url.query_pairs()
+        .find(|(key, _)| key == "page" || key == "p")
+        .and_then(|(_, value)| value.parse().ok())
+}
+
+fn marked_next(anchor: Node) -> bool {
+    let mentions_next = |node: Node, attr: &str| {
+        node.attr(attr)
+            .map(|value| value.split_whitespace().any(|word| word.contains("next")))
+            .unwrap_or(false)
+    };
+    mentions_next(anchor, "rel")
+        || mentions_next(anchor, "class")
+        || anchor
+            .parent()
+            .is_some_and(|parent| mentions_next(parent, "class"))
+}
+
+/// Link to the page after `current` in a paginated listing: an anchor marked
+/// as "next" (`rel="next"`, or a `next` class on it or its parent), else one
+/// to the same path with the `page`/`p` parameter one higher.
+pub fn next_page_link(document: &Document, current: &Url) -> Option<String> {
+    let anchors: Vec<(Node, Url)> = document
+        .find(Name("a"))
+        .filter_map(|anchor| {
+            let href = anchor.attr("href")?;
+            let url = Url::parse(&normalize_link(current, href)?).ok()?;
+            Some((anchor, url))
+        })
+        .collect();
+
+    if let Some((_, url)) = anchors.iter().find(|(anchor, _)| marked_next(*anchor)) {
+        return Some(url.to_string());
+    }
+
+    let wanted = page_number(current).unwrap_or(1) + 1;
+    anchors
+        .into_iter()
+        .find(|(_, url)| url.path() == current.path() && page_number(url) == Some(wanted))
+        .map(|(_, url)| url.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DEFAULT_ROOT_URL;
+
+    fn links_in(html: &str) -> Vec<String> {
+        links_and_diagnostics(html).0
+    }
+
+    fn links_and_diagnostics(html: &str) -> (Vec<String>, LinkDiagnostics) {
+        let document = Document::from(html);
+        let mut diagnostics = LinkDiagnostics::default();
+        let links = get_unique_links(
+            document.find(Name("a")),
+            &DiscoveryRules::from_config(&Config::default()),
+            &mut diagnostics,
+        );
+        (links, diagnostics)
+    }
+
+    #[test]
+    fn resolves_relative_links_against_root() {
+        let links = links_in(r#"<a href="/velas">Velas</a><a href="jabones">Jabones</a>"#);
+        assert_eq!(
+            links,
+            vec![
+                format!("{}/jabones", DEFAULT_ROOT_URL),
+                format!("{}/velas", DEFAULT_ROOT_URL)
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_absolute_links_on_the_same_host_only() {
+        let links = links_in(
+            r#"<a href="https://www.bathandbodyworks.mx/velas">Velas</a>
+               <a href="https://www.facebook.com/bathandbodyworks">Facebook</a>
+               <a href="//cdn.example.com/banner">Banner</a>"#,
+        );
+        assert_eq!(links, vec![format!("{}/velas", DEFAULT_ROOT_URL)]);
+    }
+
+    #[test]
+    fn skips_mailto_and_tel_links() {
+        let links = links_in(
+            r#"<a href="mailto:ayuda@bathandbodyworks.mx">Mail</a><a href="tel:+525555555555">Tel</a>"#,
+        );
+        assert!(links.is_empty());
+    }
+
+    #[test]
+    fn strips_fragments_and_collapses_duplicates() {
+        let links = links_in(
+            r#"<a href="/velas">Velas</a><a href="/velas#top">Velas</a><a href="/velas">Otra vez</a>"#,
+        );
+        assert_eq!(links, vec![format!("{}/velas", DEFAULT_ROOT_URL)]);
+    }
+
+    #[test]
+    fn ignores_anchors_without_href() {
+        let links = links_in(r#"<a name="top"></a><a href="/velas">Velas</a>"#);
+        assert_eq!(links, vec![format!("{}/velas", DEFAULT_ROOT_URL)]);
+    }
+
+    #[test]
+    fn counts_skipped_anchors_in_diagnostics() {
+        let (links, diagnostics) = links_and_diagnostics(
+            r##"<a name="top"></a>
+               <a href="">Empty</a>
+               <a href="#">Hash</a>
+               <a href="javascript:void(0)">Menu</a>
+               <a href="https://twitter.com/bbw">Twitter</a>
+               <a href="/velas">Velas</a>"##,
+        );
+        assert_eq!(links, vec![format!("{}/velas", DEFAULT_ROOT_URL)]);
+        assert_eq!(
+            diagnostics,
+            LinkDiagnostics {
+                missing_href: 1,
+                empty_href: 2,
+                pseudo_links: 1,
+                off_site: 1,
+                unfollowed_zone: 0,
+                denied: 0,
+                other_category: 0,
+                robots_disallowed: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn classifies_links_by_dom_context() {
+        let document = Document::from(
+            r#"<header><ul class="menu"><li><a href="/velas">Velas</a></li></ul></header>
+               <div class="product-item"><a href="/velas/vainilla">Vainilla</a></div>
+               <footer><a href="/terminos">Términos</a></footer>
+               <main><a href="/ofertas">Ofertas</a></main>"#,
+        );
+        let zones: Vec<LinkZone> = document.find(Name("a")).map(link_zone).collect();
+        assert_eq!(
+            zones,
+            vec![
+                LinkZone::Nav,
+                LinkZone::ProductCard,
+                LinkZone::Footer,
+                LinkZone::Content
+            ]
+        );
+    }
+
+    #[test]
+    fn follows_only_configured_zones() {
+        let (links, diagnostics) = links_and_diagnostics(
+            r#"<nav><a href="/velas">Velas</a></nav><footer><a href="/aviso-de-privacidad">Aviso</a></footer>"#,
+        );
+        assert_eq!(links, vec![format!("{}/velas", DEFAULT_ROOT_URL)]);
+        assert_eq!(diagnostics.unfollowed_zone, 1);
+    }
+
+    #[test]
+    fn skips_denied_paths() {
+        let (links, diagnostics) = links_and_diagnostics(
+            r#"<a href="/customer/account/login">Entrar</a>
+               <a href="/checkout/cart">Carrito</a>
+               <a href="/ayuda.html">Ayuda</a>
+               <a href="/carteras">Carteras</a>
+               <a href="/cartas-regalo">Cartas</a>"#,
+        );
+        assert_eq!(
+            links,
+            vec![
+                format!("{}/cartas-regalo", DEFAULT_ROOT_URL),
+                format!("{}/carteras", DEFAULT_ROOT_URL)
+            ]
+        );
+        assert_eq!(diagnostics.denied, 3);
+    }
+
+    #[test]
+    fn keeps_only_requested_categories() {
+        let document =
+            Document::from(r#"<a href="/velas">Velas</a><a href="/jabones">Jabones</a>"#);
+        let config = Config {
+            categories: vec!["Velas".to_string()],
+            ..Config::default()
+        };
+        let mut diagnostics = LinkDiagnostics::default();
+        let links = get_unique_links(
+            document.find(Name("a")),
+            &DiscoveryRules::from_config(&config),
+            &mut diagnostics,
+        );
+        assert_eq!(links, vec![format!("{}/velas", DEFAULT_ROOT_URL)]);
+        assert_eq!(diagnostics.other_category, 1);
+    }
+
+    #[test]
+    fn finds_the_next_page_from_markers_or_page_numbers() {
+        let current = Url::parse("https://www.bathandbodyworks.mx/velas?page=2").unwrap();
+        let marked = Document::from(
+            r#"<ul class="pages"><li class="pages-item-next"><a href="?page=3">Siguiente</a></li></ul>"#,
+        );
+        let numbered = Document::from(
+            r#"<a href="/velas?page=1">1</a><a href="/velas?page=3">3</a><a href="/velas?page=4">4</a>"#,
+        );
+        let last = Document::from(r#"<a href="/velas?page=1">1</a><a href="/velas?page=2">2</a>"#);
+
+        let expected = Some("https://www.bathandbodyworks.mx/velas?page=3".to_string());
+        assert_eq!(next_page_link(&marked, &current), expected);
+        assert_eq!(next_page_link(&numbered, &current), expected);
+        assert_eq!(next_page_link(&last, &current), None);
+    }
+
+    #[test]
+    fn reads_sitemap_indexes_and_url_sets() {
+        let index = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+              <sitemap><loc>https://www.bathandbodyworks.mx/sitemap-categorias.xml</loc></sitemap>
+            </sitemapindex>"#;
+        let urls = r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+              <url><loc> https://www.bathandbodyworks.mx/velas </loc></url>
+              <url><loc>https://www.bathandbodyworks.mx/customer/account</loc></url>
+              <url><loc>https://otro-sitio.example/velas</loc></url>
+              <url>
+                <loc>https://www.bathandbodyworks.mx/velas/vela-uno</loc>
+                <image:image><image:loc>https://www.bathandbodyworks.mx/media/uno.jpg</image:loc></image:image>
+              </url>
+              <url><loc>https://www.bathandbodyworks.com/p/candle.html</loc></url>
+            </urlset>"#;
+
+        let locations = sitemap_locations(index);
+        assert_eq!(
+            locations.sitemaps,
+            vec!["https://www.bathandbodyworks.mx/sitemap-categorias.xml"]
+        );
+        assert!(locations.pages.is_empty());
+
+        let locations = sitemap_locations(urls);
+        assert!(locations.sitemaps.is_empty());
+        assert_eq!(
+            locations.products,
+            vec![
+                "https://www.bathandbodyworks.mx/velas/vela-uno",
+                "https://www.bathandbodyworks.com/p/candle.html",
+            ]
+        );
+        let mut diagnostics = LinkDiagnostics::default();
+        let links = filter_sitemap_links(
+            locations.pages,
+            &DiscoveryRules::from_config(&Config::default()),
+            &mut diagnostics,
+        );
+        assert_eq!(links, vec!["https://www.bathandbodyworks.mx/velas"]);
+        assert_eq!((diagnostics.denied, diagnostics.off_site), (1, 1));
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+
+#[cfg(feature = "email")]
+use color_eyre::eyre::{eyre, WrapErr};
+#[cfg(feature = "email")]
+use color_eyre::Report;
+use fluent_bundle::FluentValue;
+#[cfg(feature = "email")]
+use lettre::message::header::ContentType;
+#[cfg(feature = "email")]
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+#[cfg(feature = "email")]
+use tracing::info;
+
+use crate::config::Config;
+use crate::discovery::canonical_link;
+use crate::filters::{discount_percent, effective_price};
+use crate::i18n::Localizer;
+use crate::site::root_for;
+use crate::BnBItem;
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn best_discount(items: &[&BnBItem]) -> f32 {
+    items
+        .iter()
+        .filter_map(|item| discount_percent(item))
+        .fold(0.0, f32::max)
+}
+
+/// Discounted items grouped by discount label like the JSON output, the
+/// deepest tiers first and at most `limit` items per tier, biggest
+/// discount first.
+pub fn tiers(items: &[BnBItem], limit: usize) -> Vec<(String, Vec<&BnBItem>)> {
+    let mut grouped: BTreeMap<&str, Vec<&BnBItem>> = BTreeMap::new();
+    for item in items {
+        if !item.discount.trim().is_empty() || item.price_promo > 0.0 {
+            grouped.entry(item.discount.trim()).or_default().push(item);
+        }
+    }
+
+    let mut tiers: Vec<(String, Vec<&BnBItem>)> = grouped
+        .into_iter()
+        .map(|(label, mut items)| {
+            items.sort_by(|a, b| {
+                discount_percent(b)
+                    .unwrap_or(0.0)
+                    .total_cmp(&discount_percent(a).unwrap_or(0.0))
+            });
+            items.truncate(limit);
+            (label.to_string(), items)
+        })
+        .collect();
+    tiers.sort_by(|(_, a), (_, b)| best_discount(b).total_cmp(&best_discount(a)));
+    tiers
+}
+
+/// The digest's subject and HTML body, listing the run's new categories
+/// ahead of the discount tiers.
+pub fn render_digest(
+    config: &Config,
+    items: &[BnBItem],
+    new_categories: &[String],
+    localizer: &Localizer,
+) -> (String, String) {
+    let tiers = tiers(items, config.digest_limit);
+    let count: usize = tiers.iter().map(|(_, items)| items.len()).sum();
+    let subject = localizer.text("email-subject", &[("count", FluentValue::from(count))]);
+
+    let mut html = format!(
+        "<html><body style=\"font-family: sans-serif\"><h1>{}</h1>",
+        escape(&subject)
+    );
+    if !new_categories.is_empty() {
+        html.push_str(&format!(
+            "<h2>{}</h2><ul>",
+            escape(&localizer.text("email-new-categories", &[]))
+        ));
+        for link in new_categories {
+            html.push_str(&format!("<li><a href=\"{0}\">{0}</a></li>", escape(link)));
+        }
+        html.push_str("</ul>");
+    }
+    for (label, items) in &tiers {
+        let label = if label.is_empty() {
+            localizer.text("report-no-discount", &[])
+        } else {
+            label.clone()
+        };
+        html.push_str(&format!(
+            "<h2>{}</h2><table cellpadding=\"4\"><tr><th align=\"left\">{}</th><th align=\"left\">{}</th><th align=\"right\">{}</th><th align=\"right\">{}</th></tr>",
+            escape(&label),
+            escape(&localizer.text("report-column-name", &[])),
+            escape(&localizer.text("report-column-type", &[])),
+            escape(&localizer.text("report-column-price", &[])),
+            escape(&localizer.text("report-column-promo", &[])),
+        ));
+        for item in items {
+            html.push_str(&format!(
+                "<tr><td><a href=\"{}\">{}</a></td><td>{}</td><td align=\"right\"><s>{:.2}</s></td><td align=\"right\"><b>{:.2}</b></td></tr>",
+                escape(&canonical_link(
+                    &root_for(&item.site, &config.root_url),
+                    &item.link
+                )),
+                escape(&item.name),
+                escape(&item.item_type),
+                item.price,
+                effective_price(item),
+            ));
+        }
+        html.push_str("</table>");
+    }
+    html.push_str("</body></html>");
+    (subject, html)
+}
+
+/// Emails the digest of the run's discounts and new categories to every
+/// `--email-to` address through `--smtp-url`. Does nothing unless both are
+/// set.
+#[cfg(feature = "email")]
+pub async fn send_digest(
+    config: &Config,
+    items: &[BnBItem],
+    new_categories: &[String],
+    localizer: &Localizer,
+) -> Result<(), Report> {
+    let smtp_url = match &config.smtp_url {
+        Some(url) if !config.email_to.is_empty() => url,
+        _ => return Ok(()),
+    };
+    let (subject, html) = render_digest(config, items, new_categories, localizer);
+
+    let mut message = Message::builder()
+        .from(config.email_from.parse().wrap_err("Invalid --email-from")?)
+        .subject(subject)
+        .header(ContentType::TEXT_HTML);
+    for to in &config.email_to {
+        message = message.to(to
+            .parse()
+            .wrap_err_with(|| format!("Invalid --email-to {:?}", to))?);
+    }
+    let message = message.body(html)?;
+
+    // The URL carries the credentials, so it is never part of an error.
+    let transport = AsyncSmtpTransport::<Tokio1Executor>::from_url(smtp_url)
+        .map_err(|_| eyre!("Invalid --smtp-url"))?
+        .build();
+    transport
+        .send(message)
+        .await
+        .map_err(|err| eyre!("Sending the digest failed: {}", err))?;
+    info!("Emailed the digest to {} recipients", config.email_to.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i18n::Lang;
+
+    #[test]
+    fn groups_discounts_into_tiers_deepest_first() {
+        let item = |name: &str, discount: &str, price_promo: f32| BnBItem {
+            name: name.to_string(),
+            link: format!("/velas/{}", name),
+            price: 100.0,
+            price_promo,
+            discount: discount.to_string(),
+            ..BnBItem::default()
+        };
+        let items = vec![
+            item("a", "20% de descuento", 80.0),
+            item("b", "2x1", 50.0),
+            item("c", "2x1", 60.0),
+            item("<d>", "20% de descuento", 85.0),
+            item("lleno", "", 0.0),
+        ];
+
+        let config = Config {
+            digest_limit: 1,
+            ..Config::default()
+        };
+        let tiers = tiers(&items, config.digest_limit);
+        let names: Vec<(&str, &str)> = tiers
+            .iter()
+            .map(|(label, items)| (label.as_str(), items[0].name.as_str()))
+            .collect();
+        assert_eq!(names, vec![("2x1", "b"), ("20% de descuento", "a")]);
+
+        let new_categories = vec!["https://www.bathandbodyworks.mx/halloween".to_string()];
+        let (_, html) = render_digest(
+            &Config::default(),
+            &items,
+            &new_categories,
+            &Localizer::new(Lang::EnUs),
+        );
+        assert!(html.contains("<li><a href=\"https://www.bathandbodyworks.mx/halloween\">"));
+        assert!(html.contains("&lt;d&gt;"));
+        assert!(html.contains("https://www.bathandbodyworks.mx/velas/b"));
+        assert!(!html.contains("lleno"));
+    }
+}
@@ -0,0 +1,727 @@
+use std::collections::HashMap;
+
+use select::document::Document;
+use select::node::Node;
+use select::predicate::{Attr, Class, Name, Predicate};
+use serde_json::Value;
+
+use crate::config::Config;
+use crate::selector::{Selector, SelectorChain, SelectorHits, SelectorProfile, JSON_LD};
+use crate::{BnBItem, ProductDetails, Provenance};
+
+// Text and classes the site uses to mark products that can't be bought.
+const SOLD_OUT_MARKERS: &[&str] = &["agotado", "sin existencias", "out of stock"];
+const SOLD_OUT_CLASSES: &[&str] = &["unavailable", "out-of-stock"];
+
+/// Extracts every product tile on a category page, de-duplicated, using
+/// the configured selector profile. Which entry of each field's selector
+/// chain matched is counted in `hits`.
+pub fn parse_products(html: &str, config: &Config, hits: &mut SelectorHits) -> Vec<BnBItem> {
+    parse_products_with(html, &config.selectors, config, hits)
+}
+
+/// Like [`parse_products`], for pages whose tiles use another class.
+pub fn parse_products_in(
+    html: &str,
+    item_class: &str,
+    config: &Config,
+    hits: &mut SelectorHits,
+) -> Vec<BnBItem> {
+    match Selector::parse(&format!(".{}", item_class)) {
+        Ok(item) => {
+            let profile = SelectorProfile {
+                item: item.into(),
+                ..config.selectors.clone()
+            };
+            parse_products_with(html, &profile, config, hits)
+        }
+        Err(_) => vec![],
+    }
+}
+
+pub fn parse_products_with(
+    html: &str,
+    profile: &SelectorProfile,
+    config: &Config,
+    hits: &mut SelectorHits,
+) -> Vec<BnBItem> {
+    let document = Document::from(html);
+    let (rank, products) = profile.item.all(&document).unwrap_or_default();
+    let json_ld = json_ld_products(&document);
+
+    let mut products_in_link = vec![];
+    for product in products {
+        hits.record("item", &profile.item, rank);
+        let mut bnb_item = BnBItem::default();
+        let mut item_hits = SelectorHits::default();
+        process_product(product, profile, &mut bnb_item, config, &mut item_hits);
+        if let Some(fallback) = json_ld.get(&link_key(&bnb_item.link)) {
+            fill_from_json_ld(&mut bnb_item, fallback, profile, &mut item_hits);
+            compute_price_with_tax(&mut bnb_item, config);
+        }
+        if config.provenance {
+            bnb_item.provenance = Some(listing_provenance(&item_hits));
+        }
+        hits.absorb(item_hits);
+
+        if !products_in_link.contains(&bnb_item) {
+            products_in_link.push(bnb_item);
+        }
+    }
+    products_in_link
+}
+
+pub fn process_product(
+    product: Node,
+    profile: &SelectorProfile,
+    bnb_item: &mut BnBItem,
+    config: &Config,
+    hits: &mut SelectorHits,
+) {
+    extract_name_and_link(product, profile, bnb_item, hits);
+    extract_item_type(product, profile, bnb_item, hits);
+    extract_price(product, profile, bnb_item, hits);
+    extract_price_promo(product, profile, bnb_item, hits);
+    extract_discount(product, profile, bnb_item, hits);
+    extract_availability(product, bnb_item);
+    extract_image_url(product, profile, bnb_item, hits);
+    compute_price_with_tax(bnb_item, config);
+}
+
+/// Lazy-loaded tiles keep the real URL in `data-src` and a placeholder in `src`.
+pub fn extract_image_url(
+    product: Node,
+    profile: &SelectorProfile,
+    bnb_item: &mut BnBItem,
+    hits: &mut SelectorHits,
+) {
+    if let Some(image_url) = process_attribute(product, "image", &profile.image, hits, |image| {
+        non_empty(
+            image
+                .attr("data-src")
+                .or_else(|| image.attr("src"))?
+                .to_string(),
+        )
+    }) {
+        bnb_item.image_url = image_url;
+    }
+}
+
+/// Whether some text says the product is sold out.
+pub fn mentions_sold_out(text: &str) -> bool {
+    let text = text.to_lowercase();
+    SOLD_OUT_MARKERS.iter().any(|marker| text.contains(marker))
+}
+
+pub fn extract_availability(product: Node, bnb_item: &mut BnBItem) {
+    let marked = product.find(Attr("class", ())).any(|node| {
+        node.attr("class")
+            .unwrap_or_default()
+            .split_whitespace()
+            .any(|class| SOLD_OUT_CLASSES.contains(&class))
+    });
+    bnb_item.available = !(marked || mentions_sold_out(&product.text()));
+}
+
+pub fn extract_discount(
+    product: Node,
+    profile: &SelectorProfile,
+    bnb_item: &mut BnBItem,
+    hits: &mut SelectorHits,
+) {
+    if let Some(discount) = process_attribute(product, "discount", &profile.discount, hits, text) {
+        bnb_item.discount = discount;
+    }
+}
+
+fn parse_price(price: Node) -> Option<f32> {
+    parse_price_text(&price.text())
+}
+
+fn parse_price_text(text: &str) -> Option<f32> {
+    text.trim().replace(['$', ','], "").parse::<f32>().ok()
+}
+
+pub fn extract_price(
+    product: Node,
+    profile: &SelectorProfile,
+    bnb_item: &mut BnBItem,
+    hits: &mut SelectorHits,
+) {
+    if let Some(price) = process_attribute(product, "price", &profile.price, hits, parse_price) {
+        bnb_item.price = price;
+    }
+}
+
+pub fn extract_price_promo(
+    product: Node,
+    profile: &SelectorProfile,
+    bnb_item: &mut BnBItem,
+    hits: &mut SelectorHits,
+) {
+    if let Some(price) = process_attribute(product, "promo", &profile.promo, hits, parse_price) {
+        bnb_item.price_promo = price;
+    }
+}
+
+pub fn extract_item_type(
+    product: Node,
+    profile: &SelectorProfile,
+    bnb_item: &mut BnBItem,
+    hits: &mut SelectorHits,
+) {
+    if let Some(item_type) = process_attribute(product, "item_type", &profile.item_type, hits, text)
+    {
+        bnb_item.item_type = item_type;
+    }
+}
+
+pub fn extract_name_and_link(
+    product: Node,
+    profile: &SelectorProfile,
+    bnb_item: &mut BnBItem,
+    hits: &mut SelectorHits,
+) {
+    if let Some(name) = process_attribute(product, "name", &profile.name, hits, text) {
+        bnb_item.name = name;
+    }
+    if let Some(link) = process_attribute(product, "link", &profile.link, hits, |link| {
+        non_empty(link.attr("href")?.to_owned())
+    }) {
+        bnb_item.link = link;
+    }
+}
+
+pub fn compute_price_with_tax(bnb_item: &mut BnBItem, config: &Config) {
+    let price = if bnb_item.price_promo > 0.0 {
+        bnb_item.price_promo
+    } else {
+        bnb_item.price
+    };
+
+    bnb_item.price_with_tax = if config.prices_exclude_iva {
+        price * (1.0 + config.iva_rate)
+    } else {
+        price
+    };
+}
+
+/// Reads a field through its chain and records which selector it came
+/// from. Only a selector whose match `read` accepts counts as a hit.
+fn process_attribute<'a, T>(
+    item: Node<'a>,
+    field: &str,
+    chain: &SelectorChain,
+    hits: &mut SelectorHits,
+    read: impl FnMut(Node<'a>) -> Option<T>,
+) -> Option<T> {
+    let (rank, value) = chain.first(item, read)?;
+    hits.record(field, chain, rank);
+    Some(value)
+}
+
+fn non_empty(text: String) -> Option<String> {
+    Some(text).filter(|text| !text.trim().is_empty())
+}
+
+fn text(node: Node) -> Option<String> {
+    non_empty(node.text())
+}
+
+/// What a page's schema.org JSON-LD says about one of its products.
+#[derive(Debug, Default, PartialEq)]
+struct JsonLdProduct {
+    name: String,
+    price: Option<f32>,
+    image: String,
+}
+
+/// The `Product`s in a page's JSON-LD blocks, including those nested in an
+/// `ItemList` or `@graph`, keyed by [`link_key`] of their URL.
+fn json_ld_products(document: &Document) -> HashMap<String, JsonLdProduct> {
+    fn collect(value: &Value, products: &mut HashMap<String, JsonLdProduct>) {
+        match value {
+            Value::Array(values) => values.iter().for_each(|value| collect(value, products)),
+            Value::Object(object) => {
+                if value["@type"] == "Product" {
+                    if let Some(url) = value["url"].as_str() {
+                        let offer = match &value["offers"] {
+                            Value::Array(offers) => offers.first().unwrap_or(&Value::Null),
+                            offer => offer,
+                        };
+                        let price = match &offer["price"] {
+                            Value::Number(price) => price.as_f64().map(|price| price as f32),
+                            Value::String(price) => parse_price_text(price),
+                            _ => None,
+                        };
+                        let image = match &value["image"] {
+                            Value::Array(images) => images.first().unwrap_or(&Value::Null),
+                            image => image,
+                        };
+                        products.insert(
+                            link_key(url),
+                            JsonLdProduct {
+                                name: value["name"]
+                                    .as_str()
+                                    .unwrap_or_default()
+                                    .trim()
+                                    .to_string(),
+                                price,
+                                image: image.as_str().unwrap_or_default().to_string(),
+                            },
+                        );
+                    }
+                }
+                object.values().for_each(|value| collect(value, products));
+            }
+            _ => {}
+        }
+    }
+
+    let mut products = HashMap::new();
+    for script in document.find(Name("script").and(Attr("type", "application/ld+json"))) {
+        if let Ok(value) = serde_json::from_str::<Value>(&script.text()) {
+            collect(&value, &mut products);
+        }
+    }
+    products
+}
+
+/// Where each field of one tile came from, given the hits recorded while
+/// extracting only that tile: `css:<selector>` or `json-ld`.
+fn listing_provenance(item_hits: &SelectorHits) -> Provenance {
+    let mut provenance = Provenance::new();
+    for (field, entries) in &item_hits.fields {
+        let field = match field.as_str() {
+            "promo" => "price_promo",
+            "image" => "image_url",
+            field => field,
+        };
+        // The JSON-LD fallback only records a hit when the selectors left
+        // the field unset (a price of 0, say), so it wins.
+        let source = match entries.keys().find(|entry| *entry == JSON_LD) {
+            Some(_) => JSON_LD.to_string(),
+            None => match entries.keys().next() {
+                Some(selector) => format!("css:{}", selector),
+                None => continue,
+            },
+        };
+        provenance.insert(field.to_string(), source);
+    }
+    provenance
+}
+
+/// A link's path without the trailing slash, so relative tile links match
+/// the absolute URLs of JSON-LD.
+fn link_key(link: &str) -> String {
+    let path = match link.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("", |slash| &rest[slash..]),
+        None => link,
+    };
+    path.trim_end_matches('/').to_string()
+}
+
+/// Fills the fields whose chains end in `"json-ld"` and that no selector
+/// could read.
+fn fill_from_json_ld(
+    bnb_item: &mut BnBItem,
+    product: &JsonLdProduct,
+    profile: &SelectorProfile,
+    hits: &mut SelectorHits,
+) {
+    let mut fill = |field: &str, chain: &SelectorChain, missing: bool, found: bool| {
+        let fill = chain.uses_json_ld() && missing && found;
+        if fill {
+            hits.record(field, chain, chain.json_ld_rank());
+        }
+        fill
+    };
+    if fill(
+        "name",
+        &profile.name,
+        bnb_item.name.trim().is_empty(),
+        !product.name.is_empty(),
+    ) {
+        bnb_item.name = product.name.clone();
+    }
+    if let Some(price) = product.price {
+        if fill("price", &profile.price, bnb_item.price == 0.0, true) {
+            bnb_item.price = price;
+        }
+    }
+    if fill(
+        "image",
+        &profile.image,
+        bnb_item.image_url.is_empty(),
+        !product.image.is_empty(),
+    ) {
+        bnb_item.image_url = product.image.clone();
+    }
+}
+
+/// Total the listing toolbar says the category has, e.g. the 24 in
+/// "Artículos 1-12 de 24". Pages without a toolbar declare nothing.
+pub fn declared_product_count(html: &str) -> Option<usize> {
+    Document::from(html)
+        .find(Class("toolbar-amount").descendant(Class("toolbar-number")))
+        .last()
+        .and_then(|number| {
+            number
+                .text()
+                .chars()
+                .filter(char::is_ascii_digit)
+                .collect::<String>()
+                .parse()
+                .ok()
+        })
+}
+
+/// Whether a page shows the site's "no products" template, judged by the
+/// configured marker texts.
+pub fn is_empty_template(html: &str, config: &Config) -> bool {
+    let text = Document::from(html)
+        .find(Name("body"))
+        .next()
+        .map(|body| body.text().to_lowercase())
+        .unwrap_or_default();
+
+    config
+        .empty_markers
+        .iter()
+        .any(|marker| text.contains(&marker.to_lowercase()))
+}
+
+fn first_text(document: &Document, predicate: impl Predicate) -> String {
+    document
+        .find(predicate)
+        .next()
+        .map(|node| node.text().trim().to_string())
+        .unwrap_or_default()
+}
+
+fn attribute_value(document: &Document, code: &str) -> String {
+    document
+        .find(Class("product").and(Class("attribute")).and(Class(code)))
+        .next()
+        .and_then(|attribute| attribute.find(Class("value")).next())
+        .map(|value| value.text().trim().to_string())
+        .unwrap_or_default()
+}
+
+/// Extracts the extra fields shown on a product's own page: the product
+/// attribute blocks, falling back to schema.org `itemprop`s, and the
+/// gallery images.
+pub fn parse_product_details(html: &str) -> ProductDetails {
+    let document = Document::from(html);
+    let itemprop = |name: &str| {
+        document
+            .find(Attr("itemprop", name))
+            .next()
+            .map(|node| {
+                node.attr("content")
+                    .map(str::to_string)
+                    .unwrap_or_else(|| node.text())
+            })
+            .map(|text| text.trim().to_string())
+            .unwrap_or_default()
+    };
+    let or_itemprop = |value: String, name: &str| {
+        if value.is_empty() {
+            itemprop(name)
+        } else {
+            value
+        }
+    };
+
+    let mut images: Vec<String> = vec![];
+    for image in document.find(Class("gallery").descendant(Name("img"))) {
+        if let Some(src) = image.attr("data-src").or_else(|| image.attr("src")) {
+            if !images.iter().any(|seen| seen == src) {
+                images.push(src.to_string());
+            }
+        }
+    }
+
+    ProductDetails {
+        sku: or_itemprop(attribute_value(&document, "sku"), "sku"),
+        description: or_itemprop(attribute_value(&document, "description"), "description"),
+        size: attribute_value(&document, "size"),
+        ingredients: attribute_value(&document, "ingredients"),
+        images,
+    }
+}
+
+/// Extracts a product from its own page, which uses different markup than
+/// the listing tiles: the title heading, the old/special price box (or the
+/// schema.org price) and the breadcrumb's category as the type.
+pub fn parse_product_page(html: &str, link: &str, config: &Config) -> BnBItem {
+    let document = Document::from(html);
+
+    // Like the listing, a field is only attributed when a value was found.
+    let mut sources = Provenance::new();
+    let mut source = |field: &str, path: &str| {
+        sources.insert(field.to_string(), format!("product-page:{}", path));
+    };
+
+    let mut name = first_text(&document, Class("page-title"));
+    if name.is_empty() {
+        name = first_text(&document, Attr("itemprop", "name"));
+        if !name.is_empty() {
+            source("name", "[itemprop=name]");
+        }
+    } else {
+        source("name", ".page-title");
+    }
+
+    let schema_price = document
+        .find(Attr("itemprop", "price"))
+        .next()
+        .map(|node| {
+            node.attr("content")
+                .map(str::to_string)
+                .unwrap_or_else(|| node.text())
+        })
+        .and_then(|price| parse_price_text(&price));
+    let old_price = parse_price_text(&first_text(
+        &document,
+        Class("old-price").descendant(Class("price")),
+    ));
+    let special_price = parse_price_text(&first_text(
+        &document,
+        Class("special-price").descendant(Class("price")),
+    ));
+    let (price, price_promo) = match (old_price, special_price) {
+        (Some(old), Some(special)) => {
+            source("price", ".old-price .price");
+            source("price_promo", ".special-price .price");
+            (old, special)
+        }
+        _ => {
+            let boxed = parse_price_text(&first_text(
+                &document,
+                Class("price-box").descendant(Class("price")),
+            ));
+            match (boxed, schema_price) {
+                (Some(_), _) => source("price", ".price-box .price"),
+                (None, Some(_)) => source("price", "[itemprop=price]"),
+                (None, None) => {}
+            }
+            (boxed.or(schema_price).unwrap_or_default(), 0.0)
+        }
+    };
+
+    let breadcrumbs: Vec<String> = document
+        .find(Class("breadcrumbs").descendant(Name("li")))
+        .map(|crumb| crumb.text().trim().to_string())
+        .collect();
+    let item_type = match breadcrumbs.len() {
+        0 | 1 => String::new(),
+        len => breadcrumbs[len - 2].clone(),
+    };
+    if !item_type.is_empty() {
+        source("item_type", ".breadcrumbs li");
+    }
+
+    let discount = match document
+        .find(Class("product-info-main"))
+        .next()
+        .and_then(|main| config.selectors.discount.first(main, text))
+    {
+        Some((rank, flag)) => {
+            source(
+                "discount",
+                &format!(
+                    ".product-info-main {}",
+                    config.selectors.discount.entry(rank)
+                ),
+            );
+            flag
+        }
+        None => String::new(),
+    };
+
+    let details = parse_product_details(html);
+    if !details.images.is_empty() {
+        source("image_url", ".gallery img");
+    }
+    let mut item = BnBItem {
+        name,
+        item_type,
+        link: link.to_string(),
+        price,
+        price_promo,
+        discount,
+        image_url: details.images.first().cloned().unwrap_or_default(),
+        available: !product_page_sold_out(html),
+        details: Some(details),
+        provenance: Some(sources).filter(|_| config.provenance),
+        ..BnBItem::default()
+    };
+    compute_price_with_tax(&mut item, config);
+    item
+}
+
+/// Whether a product page's stock block (or its schema.org availability)
+/// says it is sold out. Only those elements are checked, since the rest of
+/// the page can list other, sold-out products.
+pub fn product_page_sold_out(html: &str) -> bool {
+    let document = Document::from(html);
+    let stock_text = document
+        .find(Class("stock"))
+        .any(|stock| mentions_sold_out(&stock.text()));
+    let schema = document.find(Attr("itemprop", "availability")).any(|node| {
+        node.attr("href")
+            .or_else(|| node.attr("content"))
+            .is_some_and(|value| value.ends_with("OutOfStock"))
+    });
+    stock_text || schema
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_the_empty_listing_template() {
+        let config = Config::default();
+        let empty = r#"<body><div class="message">Lo sentimos, no encontramos productos que coincidan.</div></body>"#;
+        let listing = r#"<body><ol class="products"></ol></body>"#;
+
+        assert!(is_empty_template(empty, &config));
+        assert!(!is_empty_template(listing, &config));
+    }
+
+    #[test]
+    fn reads_the_declared_product_count() {
+        let html = r#"<p class="toolbar-amount">Artículos <span class="toolbar-number">1</span>-<span class="toolbar-number">12</span> de <span class="toolbar-number">1,024</span></p>"#;
+
+        assert_eq!(declared_product_count(html), Some(1024));
+        assert_eq!(declared_product_count("<ol class=\"products\"></ol>"), None);
+    }
+
+    #[test]
+    fn extracts_product_page_details() {
+        let html = r#"<body>
+            <div class="product attribute sku"><div class="value">026123456</div></div>
+            <div itemprop="description">Notas de calabaza y nuez.</div>
+            <div class="product attribute size"><div class="value"> 411 g </div></div>
+            <div class="gallery"><img src="/media/uno.jpg"><img data-src="/media/dos.jpg"><img src="/media/uno.jpg"></div>
+        </body>"#;
+
+        let details = parse_product_details(html);
+        assert_eq!(details.sku, "026123456");
+        assert_eq!(details.description, "Notas de calabaza y nuez.");
+        assert_eq!(details.size, "411 g");
+        assert_eq!(details.ingredients, "");
+        assert_eq!(details.images, vec!["/media/uno.jpg", "/media/dos.jpg"]);
+    }
+
+    #[test]
+    fn extracts_a_product_from_its_own_page() {
+        let html = r#"<body>
+            <div class="breadcrumbs"><ul><li>Inicio</li><li>Velas de 3 mechas</li><li>Producto Uno</li></ul></div>
+            <div class="product-info-main">
+                <h1 class="page-title"><span itemprop="name">Producto Uno</span></h1>
+                <div class="price-box">
+                    <span class="old-price"><span class="price">$1,050.00</span></span>
+                    <span class="special-price"><span class="price">$525.00</span></span>
+                </div>
+                <div class="product-item__flags--discounts"><p>50% de descuento</p></div>
+            </div>
+            <div class="gallery"><img src="/media/uno.jpg"></div>
+        </body>"#;
+
+        let config = Config {
+            provenance: true,
+            ..Config::default()
+        };
+        let item = parse_product_page(html, "/velas/producto-uno", &config);
+        assert_eq!(item.name, "Producto Uno");
+        assert_eq!(item.item_type, "Velas de 3 mechas");
+        assert_eq!((item.price, item.price_promo), (1050.0, 525.0));
+        assert_eq!(item.discount, "50% de descuento");
+        assert_eq!(item.image_url, "/media/uno.jpg");
+        assert!(item.available);
+        let provenance = item.provenance.unwrap();
+        assert_eq!(
+            provenance["price_promo"],
+            "product-page:.special-price .price"
+        );
+        assert_eq!(
+            provenance["discount"],
+            "product-page:.product-info-main .product-item__flags--discounts p"
+        );
+
+        assert_eq!(provenance["image_url"], "product-page:.gallery img");
+
+        let single = r#"<h1 class="page-title">Jabón</h1><meta itemprop="price" content="159.00">"#;
+        let item = parse_product_page(single, "", &config);
+        assert_eq!(item.price, 159.0);
+        let provenance = item.provenance.unwrap();
+        assert_eq!(provenance["price"], "product-page:[itemprop=price]");
+        assert!(!provenance.contains_key("item_type"));
+        assert!(!provenance.contains_key("image_url"));
+    }
+
+    #[test]
+    fn falls_back_to_older_markup_and_json_ld() {
+        let mut config = Config::default();
+        config.selectors.price = serde_json::from_str(
+            r#"[".product-item__price span", ".price-box .price", "json-ld"]"#,
+        )
+        .unwrap();
+        let html = r#"<body>
+            <li class="product-item"><div class="product-item__caption"><a href="/velas/uno"></a></div>
+                <div class="price-box"><span class="price">$200.00</span></div></li>
+            <li class="product-item"><div class="product-item__caption"><a href="/velas/dos/">Dos</a></div></li>
+            <script type="application/ld+json">{"@type": "ItemList", "itemListElement": [
+                {"item": {"@type": "Product", "name": "Uno", "url": "https://example.com/velas/uno"}},
+                {"item": {"@type": "Product", "name": "Dos", "url": "https://example.com/velas/dos",
+                          "offers": {"price": "150.00"}}}]}</script>
+        </body>"#;
+
+        config.provenance = true;
+        let mut hits = SelectorHits::default();
+        let items = parse_products(html, &config, &mut hits);
+        assert_eq!(items[0].name, "Uno");
+        assert_eq!(items[0].price, 200.0);
+        assert_eq!((items[1].name.as_str(), items[1].price), ("Dos", 150.0));
+        assert_eq!(hits.fallbacks["name"]["json-ld"], 1);
+        assert_eq!(hits.fallbacks["price"][".price-box .price"], 1);
+        assert_eq!(hits.fallbacks["price"]["json-ld"], 1);
+        assert!(!hits.fallbacks.contains_key("link"));
+
+        let provenance = items[1].provenance.as_ref().unwrap();
+        assert_eq!(provenance["link"], "css:.product-item__caption a");
+        assert_eq!(provenance["price"], "json-ld");
+        assert_eq!(items[0].provenance.as_ref().unwrap()["name"], "json-ld");
+    }
+
+    #[test]
+    fn flags_sold_out_tiles_and_pages() {
+        let tile = |html: &str| {
+            let document = Document::from(html);
+            let mut item = BnBItem::default();
+            extract_availability(
+                document.find(Class("product-item")).next().unwrap(),
+                &mut item,
+            );
+            item.available
+        };
+
+        assert!(tile(r#"<li class="product-item"><span>$99.00</span></li>"#));
+        assert!(!tile(
+            r#"<li class="product-item"><div class="stock">Agotado</div></li>"#
+        ));
+        assert!(!tile(
+            r#"<li class="product-item"><button class="action unavailable">Comprar</button></li>"#
+        ));
+
+        assert!(product_page_sold_out(
+            r#"<link itemprop="availability" href="https://schema.org/OutOfStock">"#
+        ));
+        assert!(!product_page_sold_out(
+            r#"<div class="stock available">En existencia</div><p>Producto agotado relacionado</p>"#
+        ));
+    }
+}
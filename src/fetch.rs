@@ -0,0 +1,68 @@
+use std::fmt;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use color_eyre::Report;
+use reqwest::{Client, ClientBuilder, StatusCode};
+
+/// Settings for the one client a command shares across all of its requests:
+/// kept-alive pooled connections and compressed responses.
+pub fn client_builder() -> ClientBuilder {
+    Client::builder()
+        .user_agent(concat!("bnbscraper/", env!("CARGO_PKG_VERSION")))
+        .gzip(true)
+        .brotli(true)
+        .pool_idle_timeout(Duration::from_secs(90))
+        .tcp_keepalive(Duration::from_secs(60))
+        .connect_timeout(Duration::from_secs(10))
+}
+
+/// Source of page bodies for the crawl. Implementations decide how a URL is
+/// turned into HTML: a live HTTP request, a headless browser or a recording.
+#[async_trait]
+pub trait Fetcher: Send + Sync {
+    async fn fetch(&self, url: &str) -> Result<String, Report>;
+}
+
+/// A page that answered with something other than a success, so its body
+/// is an error page rather than the listing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HttpStatus(pub StatusCode);
+
+impl HttpStatus {
+    /// Server errors and 429s tend to pass; a 404 stays a 404.
+    pub fn is_retryable(&self) -> bool {
+        self.0.is_server_error() || self.0 == StatusCode::TOO_MANY_REQUESTS
+    }
+}
+
+impl fmt::Display for HttpStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the site answered {}", self.0)
+    }
+}
+
+impl std::error::Error for HttpStatus {}
+
+pub struct ReqwestFetcher {
+    client: Client,
+}
+
+impl ReqwestFetcher {
+    /// Clones of a `Client` share its pool, so pass a clone of the command's
+    /// client rather than building a new one.
+    pub fn new(client: Client) -> Self {
+        ReqwestFetcher { client }
+    }
+}
+
+#[async_trait]
+impl Fetcher for ReqwestFetcher {
+    async fn fetch(&self, url: &str) -> Result<String, Report> {
+        let response = self.client.get(url).send().await?;
+        if !response.status().is_success() {
+            return Err(HttpStatus(response.status()).into());
+        }
+        Ok(response.text().await?)
+    }
+}
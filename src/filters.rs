@@ -0,0 +1,110 @@
+use crate::config::Config;
+use crate::BnBItem;
+
+/// Discount as a percentage, from the prices when there is a promo price and
+/// otherwise from a "30% de descuento" style label.
+pub fn discount_percent(item: &BnBItem) -> Option<f32> {
+    if item.price > 0.0 && item.price_promo > 0.0 && item.price_promo < item.price {
+        return Some((1.0 - item.price_promo / item.price) * 100.0);
+    }
+
+    let (before_percent, _) = item.discount.split_once('%')?;
+    before_percent
+        .trim_end()
+        .rsplit(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// The promo price when there is one, otherwise the list price.
+pub fn effective_price(item: &BnBItem) -> f32 {
+    if item.price_promo > 0.0 {
+        item.price_promo
+    } else {
+        item.price
+    }
+}
+
+/// Whether an item passes the filters requested for this run. Runs before
+/// de-duplication and any output, so dropped items are never stored.
+pub fn keep(item: &BnBItem, config: &Config) -> bool {
+    let price = effective_price(item);
+
+    config
+        .min_discount
+        .is_none_or(|min| discount_percent(item).is_some_and(|d| d >= min))
+        && config.min_price.is_none_or(|min| price >= min)
+        && config.max_price.is_none_or(|max| price <= max)
+        && (!config.require_promo || item.price_promo > 0.0)
+        && (!config.in_stock_only || item.available)
+        && (config.item_types.is_empty()
+            || config
+                .item_types
+                .iter()
+                .any(|item_type| item_type.to_lowercase() == item.item_type.trim().to_lowercase()))
+        && config
+            .name_pattern
+            .as_ref()
+            .is_none_or(|pattern| pattern.is_match(&item.name))
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    use super::*;
+
+    #[test]
+    fn discount_comes_from_prices_or_label() {
+        let promo = BnBItem {
+            price: 200.0,
+            price_promo: 150.0,
+            ..BnBItem::default()
+        };
+        assert_eq!(discount_percent(&promo), Some(25.0));
+
+        let labelled = BnBItem {
+            discount: "Hasta 40% de descuento".to_string(),
+            ..BnBItem::default()
+        };
+        assert_eq!(discount_percent(&labelled), Some(40.0));
+
+        let bundle = BnBItem {
+            discount: "2x1".to_string(),
+            ..BnBItem::default()
+        };
+        assert_eq!(discount_percent(&bundle), None);
+    }
+
+    #[test]
+    fn post_filters_scope_the_kept_items() {
+        let candle = BnBItem {
+            name: "Pumpkin Pecan Waffles".to_string(),
+            item_type: "Vela de 3 mechas".to_string(),
+            price: 650.0,
+            price_promo: 455.0,
+            ..BnBItem::default()
+        };
+        let config = Config {
+            max_price: Some(500.0),
+            require_promo: true,
+            item_types: vec!["vela de 3 mechas".to_string()],
+            name_pattern: Some(Regex::new("(?i)pumpkin").unwrap()),
+            ..Config::default()
+        };
+        assert!(keep(&candle, &config));
+
+        let full_price = BnBItem {
+            price_promo: 0.0,
+            ..candle.clone()
+        };
+        assert!(!keep(&full_price, &config));
+
+        let soap = BnBItem {
+            item_type: "Jabón de manos".to_string(),
+            ..candle
+        };
+        assert!(!keep(&soap, &config));
+    }
+}
@@ -0,0 +1,101 @@
+use clap::ValueEnum;
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource, FluentValue};
+use unic_langid::LanguageIdentifier;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Lang {
+    #[value(name = "en-US")]
+    EnUs,
+    #[value(name = "es-MX")]
+    EsMx,
+}
+
+impl Lang {
+    fn id(self) -> &'static str {
+        match self {
+            Lang::EnUs => "en-US",
+            Lang::EsMx => "es-MX",
+        }
+    }
+
+    fn source(self) -> &'static str {
+        match self {
+            Lang::EnUs => include_str!("../locales/en-US/main.ftl"),
+            Lang::EsMx => include_str!("../locales/es-MX/main.ftl"),
+        }
+    }
+}
+
+/// Report text in one language, loaded from the bundled Fluent files.
+pub struct Localizer {
+    bundle: FluentBundle<FluentResource>,
+}
+
+impl Localizer {
+    pub fn new(lang: Lang) -> Self {
+        let langid: LanguageIdentifier = lang.id().parse().expect("bundled language id is valid");
+        let resource = FluentResource::try_new(lang.source().to_string())
+            .expect("bundled Fluent file is valid");
+
+        let mut bundle = FluentBundle::new_concurrent(vec![langid]);
+        // Output goes to terminals and logs, where bidi isolation marks show up as noise.
+        bundle.set_use_isolating(false);
+        bundle
+            .add_resource(resource)
+            .expect("bundled Fluent file has no duplicate messages");
+
+        Localizer { bundle }
+    }
+
+    /// Formats message `id`; unknown ids come back as the id itself.
+    pub fn text(&self, id: &str, args: &[(&str, FluentValue)]) -> String {
+        let pattern = match self
+            .bundle
+            .get_message(id)
+            .and_then(|message| message.value())
+        {
+            Some(pattern) => pattern,
+            None => return id.to_string(),
+        };
+
+        let mut fluent_args = FluentArgs::new();
+        for (name, value) in args {
+            fluent_args.set(*name, value.clone());
+        }
+
+        let mut errors = vec![];
+        self.bundle
+            .format_pattern(pattern, Some(&fluent_args), &mut errors)
+            .into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_messages_in_each_language() {
+        let args = [("count", FluentValue::from(3))];
+        assert_eq!(
+            Localizer::new(Lang::EnUs).text("summary-total-items", &args),
+            "Total items: 3"
+        );
+        assert_eq!(
+            Localizer::new(Lang::EsMx).text("summary-total-items", &args),
+            "Productos encontrados: 3"
+        );
+    }
+
+    #[test]
+    fn every_message_exists_in_both_languages() {
+        let ids = |lang: Lang| -> Vec<String> {
+            lang.source()
+                .lines()
+                .filter_map(|line| line.split_once(" = ").map(|(id, _)| id.to_string()))
+                .collect()
+        };
+        assert_eq!(ids(Lang::EnUs), ids(Lang::EsMx));
+    }
+}
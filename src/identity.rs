@@ -0,0 +1,212 @@
+use std::collections::HashSet;
+
+use color_eyre::eyre::eyre;
+use color_eyre::Report;
+
+use crate::dedupe::normalize_name;
+use crate::BnBItem;
+
+/// One way of deciding whether two scraped items are the same product.
+/// Returns a confidence in `0.0..=1.0`, or `None` when the strategy has no
+/// opinion and the next one in the chain should decide.
+pub trait MatchStrategy: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn score(&self, a: &BnBItem, b: &BnBItem) -> Option<f32>;
+}
+
+/// Same product page means same product.
+pub struct ByLink;
+
+impl MatchStrategy for ByLink {
+    fn name(&self) -> &'static str {
+        "link"
+    }
+
+    fn score(&self, a: &BnBItem, b: &BnBItem) -> Option<f32> {
+        if !a.link.is_empty() && a.link == b.link {
+            Some(1.0)
+        } else {
+            None
+        }
+    }
+}
+
+/// Exact name and type, the scraper's historical identity.
+pub struct ByNameAndType;
+
+impl MatchStrategy for ByNameAndType {
+    fn name(&self) -> &'static str {
+        "name+type"
+    }
+
+    fn score(&self, a: &BnBItem, b: &BnBItem) -> Option<f32> {
+        if a.name == b.name && a.item_type == b.item_type {
+            Some(1.0)
+        } else {
+            None
+        }
+    }
+}
+
+/// Name and type after case, accent and punctuation folding. Scores 0.9,
+/// so it only matches with a `--match-threshold` of 0.9 or lower.
+pub struct ByNormalizedName;
+
+impl MatchStrategy for ByNormalizedName {
+    fn name(&self) -> &'static str {
+        "normalized-name+type"
+    }
+
+    fn score(&self, a: &BnBItem, b: &BnBItem) -> Option<f32> {
+        let same_type = normalize_name(&a.item_type) == normalize_name(&b.item_type);
+        if same_type && normalize_name(&a.name) == normalize_name(&b.name) {
+            Some(0.9)
+        } else {
+            None
+        }
+    }
+}
+
+/// Word overlap (Jaccard) between normalized names of items of the same type.
+pub struct FuzzyName;
+
+impl MatchStrategy for FuzzyName {
+    fn name(&self) -> &'static str {
+        "fuzzy-name"
+    }
+
+    fn score(&self, a: &BnBItem, b: &BnBItem) -> Option<f32> {
+        if normalize_name(&a.item_type) != normalize_name(&b.item_type) {
+            return None;
+        }
+
+        let a_name = normalize_name(&a.name);
+        let b_name = normalize_name(&b.name);
+        let a_words: HashSet<&str> = a_name.split(' ').collect();
+        let b_words: HashSet<&str> = b_name.split(' ').collect();
+        let union = a_words.union(&b_words).count();
+        if union == 0 {
+            return None;
+        }
+
+        Some(a_words.intersection(&b_words).count() as f32 / union as f32)
+    }
+}
+
+/// An ordered chain of strategies; the first one with an opinion decides,
+/// and its score must reach `threshold` for the items to match.
+pub struct Matcher {
+    strategies: Vec<Box<dyn MatchStrategy>>,
+    threshold: f32,
+}
+
+impl Default for Matcher {
+    fn default() -> Self {
+        Matcher::new(vec![Box::new(ByNameAndType)], 1.0)
+    }
+}
+
+/// Looks a strategy up by the name it reports.
+pub fn strategy(name: &str) -> Option<Box<dyn MatchStrategy>> {
+    let strategy: Box<dyn MatchStrategy> = match name {
+        "link" => Box::new(ByLink),
+        "name+type" => Box::new(ByNameAndType),
+        "normalized-name+type" => Box::new(ByNormalizedName),
+        "fuzzy-name" => Box::new(FuzzyName),
+        _ => return None,
+    };
+    Some(strategy)
+}
+
+impl Matcher {
+    pub fn new(strategies: Vec<Box<dyn MatchStrategy>>, threshold: f32) -> Self {
+        Matcher {
+            strategies,
+            threshold,
+        }
+    }
+
+    pub fn from_names(names: &[&str], threshold: f32) -> Result<Self, Report> {
+        let strategies = names
+            .iter()
+            .map(|&name| strategy(name).ok_or_else(|| eyre!("Unknown match strategy: {}", name)))
+            .collect::<Result<_, _>>()?;
+        Ok(Matcher::new(strategies, threshold))
+    }
+
+    /// The deciding strategy and its confidence for a pair of items.
+    pub fn score(&self, a: &BnBItem, b: &BnBItem) -> Option<(&'static str, f32)> {
+        self.strategies
+            .iter()
+            .find_map(|strategy| strategy.score(a, b).map(|score| (strategy.name(), score)))
+    }
+
+    pub fn is_match(&self, a: &BnBItem, b: &BnBItem) -> bool {
+        matches!(self.score(a, b), Some((_, score)) if score >= self.threshold)
+    }
+
+    pub fn contains(&self, items: &[BnBItem], item: &BnBItem) -> bool {
+        items.iter().any(|other| self.is_match(other, item))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(name: &str, item_type: &str, link: &str) -> BnBItem {
+        BnBItem {
+            name: name.to_string(),
+            item_type: item_type.to_string(),
+            link: link.to_string(),
+            ..BnBItem::default()
+        }
+    }
+
+    #[test]
+    fn default_matches_on_exact_name_and_type() {
+        let matcher = Matcher::default();
+        let a = item("Champagne Toast", "Body Mist", "/a");
+        assert!(matcher.is_match(&a, &item("Champagne Toast", "Body Mist", "/b")));
+        assert!(!matcher.is_match(&a, &item("champagne toast", "Body Mist", "/a")));
+    }
+
+    #[test]
+    fn first_strategy_with_an_opinion_decides() {
+        let matcher = Matcher::new(
+            vec![
+                Box::new(ByLink),
+                Box::new(ByNormalizedName),
+                Box::new(FuzzyName),
+            ],
+            0.6,
+        );
+        let a = item("Champagne Toast", "Body Mist", "/a");
+
+        assert_eq!(
+            matcher.score(&a, &item("Otro", "Vela", "/a")),
+            Some(("link", 1.0))
+        );
+        assert_eq!(
+            matcher.score(&a, &item("champagne-toast", "body mist", "/b")),
+            Some(("normalized-name+type", 0.9))
+        );
+        assert!(matcher.is_match(&a, &item("Champagne Toast Fine", "Body Mist", "/c")));
+        assert!(!matcher.is_match(&a, &item("Champagne Toast", "Vela", "/d")));
+    }
+
+    #[test]
+    fn a_mismatch_leaves_the_pair_to_the_next_strategy() {
+        let matcher = Matcher::from_names(&["name+type", "fuzzy-name"], 0.6).unwrap();
+        let a = item("Champagne Toast", "Body Mist", "/a");
+
+        assert_eq!(
+            matcher.score(&a, &item("Champagne Toast Fine", "Body Mist", "/b")),
+            Some(("fuzzy-name", 2.0 / 3.0))
+        );
+        assert_eq!(
+            matcher.score(&a, &item("Champagne Toast", "Vela", "/c")),
+            None
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use std::path::{Path, PathBuf};
+
+use color_eyre::Report;
+use futures::{stream, StreamExt};
+use reqwest::{Client, Url};
+use tokio::fs;
+use tracing::{info, warn};
+
+use crate::discovery::normalize_link;
+use crate::{BnBItem, Scraper};
+
+/// File name for an item's image: the last segment of its product link,
+/// with the extension of the image URL (`jpg` when it has none).
+pub fn image_file_name(item: &BnBItem) -> Option<String> {
+    let slug = item
+        .link
+        .split(['?', '#'])
+        .next()?
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()?
+        .trim_end_matches(".html");
+    if slug.is_empty() || item.image_url.is_empty() {
+        return None;
+    }
+
+    let image_path = item.image_url.split(['?', '#']).next()?;
+    let extension = image_path
+        .rsplit('/')
+        .next()
+        .and_then(|name| name.rsplit_once('.'))
+        .map(|(_, extension)| extension)
+        .filter(|extension| !extension.is_empty() && extension.len() <= 4)
+        .unwrap_or("jpg");
+
+    Some(format!("{}.{}", slug, extension))
+}
+
+async fn download(client: &Client, url: &str, path: &Path) -> Result<((), usize), Report> {
+    let bytes = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    fs::write(path, &bytes).await?;
+    Ok(((), bytes.len()))
+}
+
+/// Saves every item's image into `dir`, up to `--max-concurrency` at a time,
+/// and returns the files written. Each download goes through
+/// [`Scraper::request`], so it honors robots.txt and shows up in the
+/// politeness report. Images served from other hosts (CDNs) are fetched as
+/// well; failures are logged and skipped.
+pub async fn download_images(
+    scraper: &Scraper,
+    client: &Client,
+    items: &[BnBItem],
+    dir: &Path,
+) -> Result<Vec<PathBuf>, Report> {
+    let config = scraper.config();
+    fs::create_dir_all(dir).await?;
+
+    let jobs: Vec<(String, PathBuf)> = items
+        .iter()
+        .filter_map(|item| {
+            let url = normalize_link(&config.root_url, &item.image_url)
+                .or_else(|| Url::parse(&item.image_url).ok().map(Into::into))?;
+            Some((url, dir.join(image_file_name(item)?)))
+        })
+        .collect();
+
+    let total = jobs.len();
+    let mut saved: Vec<PathBuf> = stream::iter(jobs)
+        .map(|(url, path)| async move {
+            match scraper
+                .request(&url, || download(client, &url, &path))
+                .await
+            {
+                Ok(()) => Some(path),
+                Err(e) => {
+                    warn!("Failed to download {}: {}", url, e);
+                    None
+                }
+            }
+        })
+        .buffer_unordered(config.max_concurrency.max(1))
+        .filter_map(futures::future::ready)
+        .collect()
+        .await;
+    // Completion order varies, the manifest shouldn't.
+    saved.sort();
+
+    info!(
+        "Downloaded {} of {} images to {:?}",
+        saved.len(),
+        total,
+        dir
+    );
+    Ok(saved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_images_after_the_product_slug() {
+        let item = |link: &str, image_url: &str| BnBItem {
+            link: link.to_string(),
+            image_url: image_url.to_string(),
+            ..BnBItem::default()
+        };
+
+        assert_eq!(
+            image_file_name(&item(
+                "/velas/producto-uno?color=rojo",
+                "/media/a/b.png?v=2"
+            )),
+            Some("producto-uno.png".to_string())
+        );
+        assert_eq!(
+            image_file_name(&item(
+                "/velas/producto-dos.html",
+                "https://cdn.example.com/img"
+            )),
+            Some("producto-dos.jpg".to_string())
+        );
+        assert_eq!(image_file_name(&item("/velas/tres", "")), None);
+    }
+}
@@ -0,0 +1,80 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::Notify;
+use tracing::warn;
+
+// Exit status of a process killed by SIGINT, for a second Ctrl-C.
+const INTERRUPTED_STATUS: i32 = 130;
+
+/// Set when the run is asked to stop. Crawls check it between pages and
+/// stop early, keeping what they collected; a default one is never set.
+#[derive(Debug, Clone, Default)]
+pub struct Interrupt(Arc<Signal>);
+
+#[derive(Debug, Default)]
+struct Signal {
+    set: AtomicBool,
+    notify: Notify,
+}
+
+impl Interrupt {
+    /// Set by the first Ctrl-C. A second one exits at once, for when
+    /// saving the partial results hangs.
+    pub fn on_ctrl_c() -> Self {
+        let interrupt = Interrupt::default();
+        let handler = interrupt.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_err() {
+                return;
+            }
+            warn!("Interrupted, saving what was scraped so far (Ctrl-C again to quit now)");
+            handler.trigger();
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(INTERRUPTED_STATUS);
+            }
+        });
+        interrupt
+    }
+
+    pub fn trigger(&self) {
+        self.0.set.store(true, Ordering::SeqCst);
+        self.0.notify.notify_waiters();
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.0.set.load(Ordering::SeqCst)
+    }
+
+    /// Resolves once the interrupt is set.
+    pub async fn wait(&self) {
+        loop {
+            // Registered before checking, so a trigger in between isn't missed.
+            let notified = self.0.notify.notified();
+            if self.is_set() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn wakes_every_waiter() {
+        let interrupt = Interrupt::default();
+        let waiter = tokio::spawn({
+            let interrupt = interrupt.clone();
+            async move { interrupt.wait().await }
+        });
+        tokio::task::yield_now().await;
+        interrupt.trigger();
+
+        waiter.await.unwrap();
+        interrupt.wait().await;
+        assert!(interrupt.is_set());
+    }
+}
@@ -0,0 +1,134 @@
+pub mod budget;
+pub mod buffer;
+pub mod checkpoint;
+pub mod config;
+pub mod config_file;
+pub mod coverage;
+pub mod dedupe;
+pub mod demo;
+pub mod diff;
+pub mod discord;
+pub mod discovery;
+pub mod email;
+pub mod extract;
+pub mod fetch;
+pub mod filters;
+pub mod i18n;
+pub mod identity;
+pub mod images;
+pub mod interrupt;
+#[cfg(feature = "sqlite")]
+pub mod linkcheck;
+#[cfg(feature = "sqlite")]
+pub mod maintain;
+pub mod manifest;
+pub mod notify;
+pub mod output;
+pub mod politeness;
+pub mod publish;
+pub mod ratelimit;
+pub mod report;
+#[cfg(feature = "sqlite")]
+pub mod review;
+pub mod robots;
+pub mod schedule;
+pub mod scraper;
+pub mod selector;
+pub mod selftest;
+pub mod signing;
+pub mod site;
+#[cfg(feature = "sqlite")]
+pub mod store;
+pub mod telegram;
+pub mod timings;
+#[cfg(feature = "sqlite")]
+pub mod watch;
+pub mod webhook;
+pub mod yields;
+
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+pub use config::Config;
+pub use extract::parse_products;
+pub use fetch::{Fetcher, ReqwestFetcher};
+pub use scraper::{LinkResult, ScrapeRun, Scraper};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BnBItem {
+    pub name: String,
+    pub item_type: String,
+    pub link: String,
+    pub price: f32,
+    pub price_promo: f32,
+    pub price_with_tax: f32,
+    pub discount: String,
+    /// Product photo from the listing tile, as written in the page.
+    #[serde(default)]
+    pub image_url: String,
+    /// False when the tile or product page is marked sold out ("agotado").
+    #[serde(default = "available_by_default")]
+    pub available: bool,
+    /// Code of the storefront the item was scraped from, in `--site` runs.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub site: String,
+    /// Filled from the product's own page in `--deep` mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<ProductDetails>,
+    /// Which extraction path produced each field, in `--provenance` runs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+}
+
+/// Field name to the extraction path that filled it: `css:<selector>` for a
+/// listing tile, `json-ld` for the page's schema.org data, or
+/// `product-page:<selector>` for the product's own page.
+pub type Provenance = BTreeMap<String, String>;
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct ProductDetails {
+    pub sku: String,
+    pub description: String,
+    pub size: String,
+    pub ingredients: String,
+    pub images: Vec<String>,
+}
+
+fn available_by_default() -> bool {
+    true
+}
+
+impl Default for BnBItem {
+    fn default() -> Self {
+        BnBItem {
+            name: String::new(),
+            item_type: String::new(),
+            link: String::new(),
+            price: 0.0,
+            price_promo: 0.0,
+            price_with_tax: 0.0,
+            discount: String::new(),
+            image_url: String::new(),
+            available: available_by_default(),
+            site: String::new(),
+            details: None,
+            provenance: None,
+        }
+    }
+}
+
+impl PartialEq for BnBItem {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.item_type == other.item_type
+    }
+}
+
+/// Seconds since the Unix epoch, the timestamp format of every stored record.
+pub(crate) fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+
+use color_eyre::eyre::eyre;
+use color_eyre::Report;
+use reqwest::header::LOCATION;
+use reqwest::redirect::Policy;
+use reqwest::{StatusCode, Url};
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::fetch::{client_builder, ReqwestFetcher};
+use crate::politeness::CrawlLog;
+use crate::robots::Disallowed;
+use crate::store::SqliteStore;
+use crate::Scraper;
+
+/// Outcome of a HEAD request against a stored product link.
+#[derive(Debug, PartialEq)]
+pub enum LinkStatus {
+    Ok,
+    Redirected(String),
+    Dead,
+    Error,
+}
+
+impl LinkStatus {
+    pub fn classify(status: StatusCode, location: Option<&str>) -> Self {
+        if status.is_success() {
+            LinkStatus::Ok
+        } else if status.is_redirection() {
+            LinkStatus::Redirected(location.unwrap_or_default().to_string())
+        } else if status == StatusCode::NOT_FOUND || status == StatusCode::GONE {
+            LinkStatus::Dead
+        } else {
+            LinkStatus::Error
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            LinkStatus::Ok => "ok",
+            LinkStatus::Redirected(_) => "redirected",
+            LinkStatus::Dead => "dead",
+            LinkStatus::Error => "error",
+        }
+    }
+}
+
+/// HEADs up to `sample` stored product links, least recently checked first,
+/// and records what came back in the store. Each link goes through a
+/// [`Scraper`] for its own site, so it answers to that site's robots.txt and
+/// Crawl-delay, and the requests land in `--politeness-report`.
+pub async fn verify_links(config: &Config, sample: usize) -> Result<(), Report> {
+    let store = config
+        .store
+        .as_deref()
+        .ok_or_else(|| eyre!("verify-links needs --store to read product links from"))?;
+    let store = SqliteStore::open(store)?;
+    // Redirects are what we're looking for, so don't follow them.
+    let client = client_builder().redirect(Policy::none()).build()?;
+    let pages = client_builder().build()?;
+    let mut scrapers: BTreeMap<String, Scraper> = BTreeMap::new();
+
+    let (mut dead, mut redirected, mut disallowed) = (0, 0, 0);
+    for (product_id, link) in store.links_to_verify(sample)? {
+        let root = Url::parse(&link)
+            .and_then(|url| url.join("/"))
+            .unwrap_or_else(|_| config.root_url.clone());
+        let scraper = scrapers.entry(root.to_string()).or_insert_with(|| {
+            let site = Config {
+                root_url: root,
+                ..config.clone()
+            };
+            Scraper::new(ReqwestFetcher::new(pages.clone()), site)
+        });
+        let head = || async { Ok((client.head(&link).send().await?, 0)) };
+        let (status, http_status) = match scraper.request(&link, head).await {
+            Err(err) if err.downcast_ref::<Disallowed>().is_some() => {
+                disallowed += 1;
+                continue;
+            }
+            Ok(response) => {
+                let location = response
+                    .headers()
+                    .get(LOCATION)
+                    .and_then(|value| value.to_str().ok());
+                (
+                    LinkStatus::classify(response.status(), location),
+                    Some(response.status().as_u16()),
+                )
+            }
+            Err(e) => {
+                warn!("Checking {} failed: {}", link, e);
+                (LinkStatus::Error, None)
+            }
+        };
+
+        let redirect_to = match &status {
+            LinkStatus::Dead => {
+                dead += 1;
+                None
+            }
+            LinkStatus::Redirected(to) => {
+                redirected += 1;
+                Some(to.as_str())
+            }
+            _ => None,
+        };
+        store.record_link_check(product_id, status.label(), http_status, redirect_to)?;
+    }
+
+    info!(
+        "Link check finished: {} dead, {} redirected, {} skipped for robots.txt",
+        dead, redirected, disallowed
+    );
+    if let Some(path) = &config.politeness_report {
+        let mut crawl = CrawlLog::default();
+        for scraper in scrapers.values() {
+            crawl.absorb(scraper.crawl_log(0));
+        }
+        crawl.write_report(path, config)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_head_responses() {
+        assert_eq!(LinkStatus::classify(StatusCode::OK, None), LinkStatus::Ok);
+        assert_eq!(
+            LinkStatus::classify(StatusCode::MOVED_PERMANENTLY, Some("/velas/nuevo")),
+            LinkStatus::Redirected("/velas/nuevo".to_string())
+        );
+        assert_eq!(
+            LinkStatus::classify(StatusCode::GONE, None),
+            LinkStatus::Dead
+        );
+        assert_eq!(
+            LinkStatus::classify(StatusCode::SERVICE_UNAVAILABLE, None),
+            LinkStatus::Error
+        );
+    }
+}
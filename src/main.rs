@@ -1,192 +1,322 @@
-use std::collections::{HashMap, HashSet};
-use std::fs::File;
-
+use bnbscraper::budget::RunBudget;
+use bnbscraper::config::Config;
+#[cfg(feature = "email")]
+use bnbscraper::email;
+use bnbscraper::fetch::client_builder;
+use bnbscraper::i18n::Localizer;
+use bnbscraper::interrupt::Interrupt;
+#[cfg(feature = "sqlite")]
+use bnbscraper::maintain::{self, MaintainOptions};
+use bnbscraper::report::{self, ReportStyle};
+#[cfg(feature = "sqlite")]
+use bnbscraper::review::{self, ReviewAction};
+use bnbscraper::schedule::{self, Cron, Schedule};
+use bnbscraper::{
+    checkpoint, coverage, dedupe, demo, diff, discord, images, notify, publish, selftest, signing,
+    telegram, webhook, ReqwestFetcher, ScrapeRun, Scraper,
+};
+#[cfg(feature = "sqlite")]
+use bnbscraper::{linkcheck, watch};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use color_eyre::eyre::eyre;
 use color_eyre::Report;
-use futures::stream::FuturesUnordered;
-use futures::StreamExt;
 use reqwest::Client;
-use select::document::{Document, Find};
-use select::node::Node;
-use select::predicate::{Class, Name, Predicate};
-use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
 use tracing::info;
 use tracing_subscriber::EnvFilter;
 
-const ROOT_URL: &str = "https://www.bathandbodyworks.mx";
+#[derive(Parser, Debug)]
+#[command(
+    name = "bnbscraper",
+    version,
+    about = "Scrapes deals from Bath & Body Works México"
+)]
+struct Cli {
+    #[command(flatten)]
+    config: Config,
 
-#[derive(Serialize, Deserialize, Debug, Default)]
-struct BnBItem {
-    name: String,
-    item_type: String,
-    link: String,
-    price: f32,
-    price_promo: f32,
-    discount: String,
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-impl PartialEq for BnBItem {
-    fn eq(&self, other: &Self) -> bool {
-        self.name == other.name && self.item_type == other.item_type
-    }
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Crawl the site and write the grouped JSON output (the default)
+    Scrape,
+    /// Keep running and scrape on a schedule, saving, diffing and notifying
+    /// each time like `scrape`. A failed scrape is logged and retried at the
+    /// next slot
+    Daemon {
+        /// Time between scrapes, e.g. 6h or 1h30m
+        #[arg(long, value_parser = schedule::parse_interval, required_unless_present = "cron")]
+        every: Option<Duration>,
+        /// Five-field cron expression in UTC, e.g. "0 */6 * * *"
+        #[arg(long, conflicts_with = "every")]
+        cron: Option<String>,
+    },
+    /// Scrape the site's search results for a keyword instead of crawling.
+    /// Writes --output but leaves the yield history and store alone
+    SearchSite {
+        /// Keyword to search for, e.g. a scent
+        query: String,
+    },
+    /// Print the latest output as a report
+    Report {
+        /// Layout of the report
+        #[arg(long, value_enum, default_value_t = ReportStyle::Table)]
+        style: ReportStyle,
+    },
+    /// Compare two scrapes: new products, removed products and price changes
+    Diff {
+        /// Earlier output file, or a sqlite:// store
+        #[arg(long)]
+        previous: String,
+        /// Later output file or sqlite:// store; defaults to --output. Passing
+        /// the same store as --previous compares its last two runs
+        #[arg(long)]
+        current: Option<String>,
+        /// JSON file of webhook templates to call for each new, removed or
+        /// repriced product
+        #[arg(long)]
+        webhooks: Option<PathBuf>,
+    },
+    /// HEAD a sample of product links from --store and record dead or
+    /// redirected ones
+    #[cfg(feature = "sqlite")]
+    VerifyLinks {
+        /// How many links to check, least recently checked first
+        #[arg(long, default_value_t = 100)]
+        sample: usize,
+    },
+    /// Validate the latest output, checksum it and publish it as a new version
+    /// in a directory, updating its latest.json pointer
+    Publish {
+        /// Directory served to consumers, e.g. a GitHub Pages checkout
+        #[arg(long)]
+        to: PathBuf,
+    },
+    /// Check that the files listed in the manifest are unchanged
+    VerifyManifest {
+        /// Hex ed25519 public key to also check manifest.json.sig against
+        #[arg(long)]
+        public_key: Option<String>,
+    },
+    /// Scrape only the product pages listed in a file and record their prices
+    #[cfg(feature = "sqlite")]
+    Watch {
+        /// File with one product URL per line; `#` starts a comment
+        watchlist: PathBuf,
+        /// Store the watched prices are recorded in, apart from --store
+        #[arg(long, default_value = "sqlite://watchlist.db")]
+        history: String,
+    },
+    /// Run the routine upkeep tasks (store backup, schema migration, product
+    /// de-duplication, version rotation) and summarize each one's status
+    #[cfg(feature = "sqlite")]
+    Maintain {
+        /// Publish destination whose old versions are deleted
+        #[arg(long)]
+        archive: Option<PathBuf>,
+        /// Published versions kept in --archive
+        #[arg(long, default_value_t = 30)]
+        keep_versions: usize,
+        /// Directory --store is backed up into
+        #[arg(long)]
+        backup_dir: Option<PathBuf>,
+        /// Backups kept in --backup-dir
+        #[arg(long, default_value_t = 7)]
+        keep_backups: usize,
+    },
+    /// List, accept or reject the prices --review-factor held back from
+    /// --store
+    #[cfg(feature = "sqlite")]
+    Review {
+        #[command(subcommand)]
+        action: ReviewAction,
+    },
+    /// Chart how often each field was extracted over the recorded runs
+    Coverage,
+    /// Print clusters of near-duplicate items from the latest output
+    DedupeReport,
+    /// Check extraction against the bundled golden pages
+    Selftest,
+    /// Show what the tool produces by running it against bundled pages,
+    /// without network access
+    Demo,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Report> {
     setup()?;
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches)?;
+    cli.config.merge_file(&matches)?;
+    let localizer = Localizer::new(cli.config.lang);
 
-    info!("Starting Bath And Body Works scraper...");
-
-    let client = Client::new();
-    let res = client.get(ROOT_URL).send().await?.text().await?;
-
-    let document = Document::from(res.as_str());
-    let links = document.find(Name("a"));
-    let uniq_links: Vec<String> = get_unique_links(links);
-
-    info!("Landing page links fetched...");
-
-    let mut all_items: Vec<BnBItem> = Vec::new();
-    let mut items_futures = uniq_links
-        .iter()
-        .map(|link| process_link(link))
-        .collect::<FuturesUnordered<_>>();
-
-    while let Some(result) = items_futures.next().await {
-        if let Ok(products) = result {
-            for product in products {
-                if !all_items.contains(&product) {
-                    all_items.push(product);
-                }
+    match cli.command.unwrap_or(Command::Scrape) {
+        Command::Scrape => {
+            let client = client_builder().build()?;
+            scrape(&client, cli.config, &Interrupt::on_ctrl_c(), &localizer).await
+        }
+        Command::Daemon { every, cron } => {
+            let schedule = match (every, cron) {
+                (Some(interval), _) => Schedule::Every(interval),
+                (None, Some(cron)) => Schedule::Cron(Cron::parse(&cron)?),
+                (None, None) => unreachable!("clap requires --every or --cron"),
+            };
+            // One client for every cycle so connections are reused.
+            let client = client_builder().build()?;
+            let config = &cli.config;
+            let interrupt = Interrupt::on_ctrl_c();
+            schedule::run_daemon(&schedule, &interrupt, || {
+                scrape(&client, config.clone(), &interrupt, &localizer)
+            })
+            .await
+        }
+        Command::SearchSite { query } => search_site(cli.config, &query, &localizer).await,
+        Command::Report { style } => {
+            report::run(&cli.config.require_json_output()?, style, &localizer)
+        }
+        Command::Diff {
+            previous,
+            current,
+            webhooks,
+        } => {
+            let current = match current {
+                Some(current) => current,
+                None => cli
+                    .config
+                    .require_json_output()?
+                    .to_string_lossy()
+                    .into_owned(),
+            };
+            let diff = diff::run(&previous, &current, &cli.config.root_url, &localizer)?;
+            let client = client_builder().build()?;
+            if let Some(path) = webhooks {
+                let templates = webhook::load_templates(&path)?;
+                webhook::push(&client, &templates, &diff).await?;
             }
+            telegram::notify(&client, &cli.config, &diff, &localizer).await?;
+            discord::notify(&client, &cli.config, &diff, &localizer).await
         }
+        #[cfg(feature = "sqlite")]
+        Command::VerifyLinks { sample } => linkcheck::verify_links(&cli.config, sample).await,
+        Command::Publish { to } => publish::publish(&cli.config, &to).map(|_| ()),
+        Command::VerifyManifest { public_key } => {
+            signing::verify_manifest(&cli.config.manifest_path(), public_key.as_deref())
+        }
+        #[cfg(feature = "sqlite")]
+        Command::Watch { watchlist, history } => {
+            let client = client_builder().build()?;
+            let scraper = Scraper::new(ReqwestFetcher::new(client), cli.config);
+            watch::watch(&scraper, &watchlist, &history, &localizer).await
+        }
+        #[cfg(feature = "sqlite")]
+        Command::Maintain {
+            archive,
+            keep_versions,
+            backup_dir,
+            keep_backups,
+        } => {
+            let options = MaintainOptions {
+                archive,
+                keep_versions,
+                backup_dir,
+                keep_backups,
+            };
+            maintain::run(&cli.config, &options, &localizer)
+        }
+        #[cfg(feature = "sqlite")]
+        Command::Review { action } => review::run(&cli.config, action, &localizer),
+        Command::Coverage => coverage::report(&cli.config.yield_history_path(), &localizer),
+        Command::DedupeReport => dedupe::report(&cli.config.require_json_output()?, &localizer),
+        Command::Selftest => selftest::run(),
+        Command::Demo => demo::run(&localizer).await,
     }
-
-    info!("Finished!");
-    info!("Total items: {}", all_items.len());
-
-    let mut grouped: HashMap<&str, Vec<&BnBItem>> = HashMap::new();
-    for item in all_items.iter() {
-        grouped
-            .entry(item.discount.as_str())
-            .or_insert_with(Vec::new)
-            .push(item);
-    }
-
-    let json_file = "/Users/otniel/Documents/code/rust/bnbscraper/data.json";
-    serde_json::to_writer(&File::create(json_file)?, &grouped)?;
-
-    Ok(())
 }
 
-async fn process_link(link: &str) -> Result<Vec<BnBItem>, Report> {
-    info!("Processing link: {}", link);
-    let res = reqwest::get(link).await?.text().await?;
-    let document = Document::from(res.as_str());
-    let products = document.find(Class("product-item"));
-
-    let mut products_in_link = vec![];
-    for product in products {
-        let mut bnb_item = BnBItem::default();
-        process_product(product, &mut bnb_item);
-
-        if !products_in_link.contains(&bnb_item) {
-            products_in_link.push(bnb_item);
+async fn scrape(
+    client: &Client,
+    config: Config,
+    interrupt: &Interrupt,
+    localizer: &Localizer,
+) -> Result<(), Report> {
+    // Read before the run replaces it, to alert on what changed since.
+    let previous = match config.json_output() {
+        Some(json) if json.exists() => Some(diff::load_items(&json.to_string_lossy(), 0)?),
+        _ => None,
+    };
+    let mut run = ScrapeRun::default();
+    let budget = RunBudget::new(config.max_runtime());
+    for site_config in config.site_configs() {
+        if interrupt.is_set() {
+            break;
+        }
+        if let Some(site) = site_config.site {
+            info!("Scraping {}", site.root_url());
         }
+        let scraper = Scraper::new(ReqwestFetcher::new(client.clone()), site_config)
+            .with_budget(budget)
+            .with_interrupt(interrupt.clone());
+        let mut site_run = scraper.scrape_all().await?;
+        let site_config = scraper.config();
+        // Before saving, so the manifest lists and signs the images too, and
+        // through the site's scraper, so they follow its robots.txt.
+        if let (Some(dir), false) = (&site_config.download_images, site_run.partial) {
+            site_run.images =
+                images::download_images(&scraper, client, &site_run.items, dir).await?;
+            site_run.crawl.absorb(scraper.crawl_log(0));
+        }
+        // Each site keeps its own output and history apart from the others.
+        if config.partitioned() {
+            site_run.save(site_config)?;
+        }
+        run.absorb(site_run);
     }
-    Ok(products_in_link)
-}
-
-fn process_product(product: Node, mut bnb_item: &mut BnBItem) {
-    extract_name_and_link(product, &mut bnb_item);
-    extract_item_type(product, &mut bnb_item);
-    extract_price(product, &mut bnb_item);
-    extract_price_promo(product, &mut bnb_item);
-    extract_discount(product, &mut bnb_item);
-}
-
-fn get_unique_links(links: Find<Name<&str>>) -> Vec<String> {
-    links
-        .into_iter()
-        .map(|node| node.attr("href").unwrap())
-        .collect::<HashSet<_>>()
-        .into_iter()
-        .filter(|link| !link.contains("www"))
-        .map(|link| format!("{}{}", ROOT_URL, link))
-        .collect()
-}
+    // Also partial when the interrupt came between two sites.
+    run.partial |= interrupt.is_set();
+    run.save(&config)?;
+    if run.partial {
+        // The checkpoint stays for --resume, and alerts wait for a full run.
+        return Err(eyre!(
+            "Interrupted; wrote the {} items scraped so far",
+            run.items.len()
+        ));
+    }
+    checkpoint::clear(&config)?;
 
-fn extract_discount(product: Node, bnb_item: &mut BnBItem) {
-    process_attribute(
-        product,
-        Class("product-item__flags--discounts"),
-        Name("p"),
-        |discount: Node| {
-            bnb_item.discount = discount.text();
-        },
-    );
-}
+    let mut changes = previous.map(|previous| diff::diff(&previous, &run.items, &config.root_url));
+    // The history knows about new categories even without a previous output.
+    if !run.new_categories.is_empty() {
+        changes.get_or_insert_with(Default::default).new_categories = run.new_categories.clone();
+    }
+    if let Some(changes) = &changes {
+        telegram::notify(client, &config, changes, localizer).await?;
+        discord::notify(client, &config, changes, localizer).await?;
+    }
+    notify::post_results(client, &config, &run.grouped(), changes.as_ref()).await?;
+    #[cfg(feature = "email")]
+    email::send_digest(&config, &run.items, &run.new_categories, localizer).await?;
 
-fn extract_price(product: Node, bnb_item: &mut BnBItem) {
-    process_attribute(
-        product,
-        Class("product-item__price"),
-        Name("span"),
-        |price: Node| {
-            let price = price.text().replace("$", "");
-            let parsed_price = price.parse::<f32>();
-            if let Ok(parsed_price) = parsed_price {
-                bnb_item.price = parsed_price;
-            }
-        },
-    );
-}
+    for line in run.summary(localizer) {
+        info!("{}", line);
+    }
+    run.timings.report(localizer);
 
-fn extract_price_promo(product: Node, bnb_item: &mut BnBItem) {
-    process_attribute(
-        product,
-        Class("product-item__price"),
-        Class("price-new"),
-        |price: Node| {
-            let price = price.text().replace("$", "");
-            let parsed_price = price.parse::<f32>();
-            if let Ok(parsed_price) = parsed_price {
-                bnb_item.price_promo = parsed_price;
-            }
-        },
-    );
-}
-fn extract_item_type(product: Node, bnb_item: &mut BnBItem) {
-    process_attribute(
-        product,
-        Class("product-item__form"),
-        Name("li"),
-        |item_type: Node| {
-            bnb_item.item_type = item_type.text();
-        },
-    );
+    Ok(())
 }
 
-fn extract_name_and_link(product: Node, bnb_item: &mut BnBItem) {
-    process_attribute(
-        product,
-        Class("product-item__caption"),
-        Name("a"),
-        |caption: Node| {
-            bnb_item.name = caption.text();
-            bnb_item.link = caption.attr("href").unwrap().to_owned();
-        },
-    );
-}
+async fn search_site(config: Config, query: &str, localizer: &Localizer) -> Result<(), Report> {
+    let scraper = Scraper::new(ReqwestFetcher::new(client_builder().build()?), config);
+    let run = scraper.scrape_search(query).await?;
+    run.write_output(scraper.config())?;
 
-fn process_attribute<T>(item: Node, class: Class<&str>, predicate: T, mut handler: impl FnMut(Node))
-where
-    T: Predicate,
-{
-    let link_node = item.find(class.descendant(predicate)).next();
+    for line in run.summary(localizer) {
+        info!("{}", line);
+    }
 
-    if let Some(unwrapped_node) = link_node {
-        handler(unwrapped_node);
-    };
+    Ok(())
 }
 
 fn setup() -> Result<(), Report> {
@@ -195,11 +325,9 @@ fn setup() -> Result<(), Report> {
     }
     color_eyre::install()?;
 
-    if std::env::var("RUST_LOG").is_err() {
-        std::env::set_var("RUST_LOG", "info")
-    }
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
     tracing_subscriber::fmt::fmt()
-        .with_env_filter(EnvFilter::from_default_env())
+        .with_env_filter(filter)
         .init();
 
     Ok(())
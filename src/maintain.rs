@@ -0,0 +1,213 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::eyre;
+use color_eyre::Report;
+use fluent_bundle::FluentValue;
+
+use crate::config::Config;
+use crate::i18n::Localizer;
+use crate::publish::rotate_versions;
+use crate::store::SqliteStore;
+use crate::unix_timestamp;
+
+/// What `maintain` may touch besides the configured store.
+#[derive(Debug, Clone)]
+pub struct MaintainOptions {
+    /// Publish destination whose old versions are rotated out
+    pub archive: Option<PathBuf>,
+    pub keep_versions: usize,
+    /// Directory the store is backed up into
+    pub backup_dir: Option<PathBuf>,
+    pub keep_backups: usize,
+}
+
+#[derive(Debug)]
+pub enum TaskOutcome {
+    Done(String),
+    Skipped(String),
+    Failed(String),
+}
+
+impl TaskOutcome {
+    fn status(&self) -> &'static str {
+        match self {
+            TaskOutcome::Done(_) => "done",
+            TaskOutcome::Skipped(_) => "skipped",
+            TaskOutcome::Failed(_) => "failed",
+        }
+    }
+
+    fn detail(&self) -> &str {
+        match self {
+            TaskOutcome::Done(detail)
+            | TaskOutcome::Skipped(detail)
+            | TaskOutcome::Failed(detail) => detail,
+        }
+    }
+}
+
+fn outcome(result: Result<TaskOutcome, Report>) -> TaskOutcome {
+    result.unwrap_or_else(|err| TaskOutcome::Failed(format!("{:#}", err)))
+}
+
+/// Runs every maintenance task, each one even if an earlier one failed, and
+/// prints one status line per task. Fails if any task failed, so a cron job
+/// notices.
+pub fn run(
+    config: &Config,
+    options: &MaintainOptions,
+    localizer: &Localizer,
+) -> Result<(), Report> {
+    let store = config.store.as_deref();
+    let text = |id: &str, args: &[(&str, FluentValue)]| localizer.text(id, args);
+    let no_store = || Ok(TaskOutcome::Skipped(text("maintain-no-store", &[])));
+
+    // Back up first, so the copy predates anything the other tasks change.
+    let tasks: Vec<(&str, TaskOutcome)> = vec![
+        (
+            "backup",
+            outcome(match (store, &options.backup_dir) {
+                (None, _) => no_store(),
+                (_, None) => Ok(TaskOutcome::Skipped(text("maintain-no-backup-dir", &[]))),
+                (Some(store), Some(dir)) => {
+                    backup(store, dir, options.keep_backups).map(|(path, pruned)| {
+                        TaskOutcome::Done(text(
+                            "maintain-backed-up",
+                            &[
+                                ("path", path.to_string_lossy().into_owned().into()),
+                                ("pruned", FluentValue::from(pruned)),
+                            ],
+                        ))
+                    })
+                }
+            }),
+        ),
+        (
+            "migrate",
+            outcome(match store {
+                None => no_store(),
+                Some(store) => SqliteStore::open(store)
+                    .and_then(|store| store.migrate())
+                    .map(|previous| {
+                        TaskOutcome::Done(text(
+                            "maintain-migrated",
+                            &[("previous", FluentValue::from(previous))],
+                        ))
+                    }),
+            }),
+        ),
+        (
+            "dedupe-registry",
+            outcome(match store {
+                None => no_store(),
+                Some(store) => SqliteStore::open(store)
+                    .and_then(|mut store| store.dedupe_products(&config.root_url))
+                    .map(|merged| {
+                        TaskOutcome::Done(text(
+                            "maintain-deduped",
+                            &[("count", FluentValue::from(merged))],
+                        ))
+                    }),
+            }),
+        ),
+        (
+            "prune-cache",
+            TaskOutcome::Skipped(text("maintain-no-cache", &[])),
+        ),
+        (
+            "rotate-archives",
+            outcome(match &options.archive {
+                None => Ok(TaskOutcome::Skipped(text("maintain-no-archive", &[]))),
+                Some(dir) => rotate_versions(dir, options.keep_versions).map(|deleted| {
+                    TaskOutcome::Done(text(
+                        "maintain-rotated",
+                        &[("count", FluentValue::from(deleted))],
+                    ))
+                }),
+            }),
+        ),
+    ];
+
+    for (task, outcome) in &tasks {
+        println!(
+            "{}",
+            text(
+                "maintain-task",
+                &[
+                    ("task", (*task).into()),
+                    ("status", outcome.status().into()),
+                    ("detail", outcome.detail().into()),
+                ],
+            )
+        );
+    }
+
+    let failed = tasks
+        .iter()
+        .filter(|(_, outcome)| matches!(outcome, TaskOutcome::Failed(_)))
+        .count();
+    match failed {
+        0 => Ok(()),
+        failed => Err(eyre!("{} maintenance tasks failed", failed)),
+    }
+}
+
+/// Copies the store into `dir` as `<name>-<timestamp>.db` and deletes all but
+/// the `keep` newest such copies. Returns the new copy and the number deleted.
+fn backup(store: &str, dir: &Path, keep: usize) -> Result<(PathBuf, usize), Report> {
+    let source = store
+        .strip_prefix("sqlite://")
+        .ok_or_else(|| eyre!("Only sqlite:// stores can be backed up"))?;
+    let stem = Path::new(source)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "store".to_string());
+
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{}-{}.db", stem, unix_timestamp()));
+    if path.exists() {
+        return Err(eyre!("{:?} already exists", path));
+    }
+    SqliteStore::open(store)?.backup(&path)?;
+
+    let prefix = format!("{}-", stem);
+    let mut backups: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            name.starts_with(&prefix) && name.ends_with(".db")
+        })
+        .collect();
+    backups.sort();
+
+    let expired = backups.len().saturating_sub(keep.max(1));
+    for old in backups.iter().take(expired) {
+        fs::remove_file(old)?;
+    }
+    Ok((path, expired))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backs_up_the_store_and_keeps_the_newest_copies() {
+        let dir = std::env::temp_dir().join(format!("bnbscraper-maintain-{}", std::process::id()));
+        let backups = dir.join("backups");
+        fs::create_dir_all(&backups).unwrap();
+        let store = format!("sqlite://{}", dir.join("prices.db").display());
+        SqliteStore::open(&store).unwrap();
+        fs::write(backups.join("prices-1.db"), "").unwrap();
+        fs::write(backups.join("prices-2.db"), "").unwrap();
+
+        let (path, pruned) = backup(&store, &backups, 2).unwrap();
+        let copied = SqliteStore::open_path(&path).unwrap().migrate();
+        let left = fs::read_dir(&backups).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(copied.is_ok());
+        assert_eq!((pruned, left), (1, 2));
+    }
+}
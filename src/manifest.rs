@@ -0,0 +1,84 @@
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use color_eyre::Report;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::output::write_json_atomically;
+use crate::unix_timestamp;
+
+/// One file written by a run.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Artifact {
+    pub path: PathBuf,
+    /// What the file is: "output", "yield-history", "store", ...
+    pub kind: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl Artifact {
+    pub fn describe(path: &Path, kind: &str) -> Result<Self, Report> {
+        Ok(Artifact {
+            path: path.to_path_buf(),
+            kind: kind.to_string(),
+            size: path.metadata()?.len(),
+            sha256: sha256_file(path)?,
+        })
+    }
+}
+
+/// Every artifact of one run, so downstream jobs can check what they fetch.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Manifest {
+    pub generated_at: u64,
+    pub artifacts: Vec<Artifact>,
+    /// The run was interrupted and its output holds only what it got to.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub partial: bool,
+}
+
+impl Manifest {
+    pub fn new(artifacts: Vec<Artifact>) -> Self {
+        Manifest {
+            generated_at: unix_timestamp(),
+            artifacts,
+            partial: false,
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Report> {
+        write_json_atomically(path, self)
+    }
+}
+
+/// Hex SHA-256 of a file's contents.
+pub fn sha256_file(path: &Path) -> Result<String, Report> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn describes_artifacts_by_size_and_hash() {
+        let path = std::env::temp_dir().join(format!("bnbscraper-{}.manifest", std::process::id()));
+        fs::write(&path, "abc").unwrap();
+
+        let artifact = Artifact::describe(&path, "output").unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(artifact.size, 3);
+        assert_eq!(
+            artifact.sha256,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
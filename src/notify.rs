@@ -0,0 +1,150 @@
+use std::time::Duration;
+
+use color_eyre::eyre::eyre;
+use color_eyre::Report;
+use reqwest::header::{self, HeaderMap, HeaderName, HeaderValue};
+use reqwest::Client;
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::config::{Config, WebhookPayload};
+use crate::diff::RunDiff;
+
+/// Parses `--webhook-header` values written as `Name: value`.
+pub fn headers(config: &Config) -> Result<HeaderMap, Report> {
+    let mut headers = HeaderMap::new();
+    for line in &config.webhook_headers {
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| eyre!("Webhook header {:?} is not `Name: value`", line))?;
+        let name = HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|_| eyre!("Invalid webhook header name {:?}", name.trim()))?;
+        // Header values are often credentials, so they stay out of errors.
+        let value = HeaderValue::from_str(value.trim())
+            .map_err(|_| eyre!("Invalid value for webhook header {}", name))?;
+        headers.append(name, value);
+    }
+    Ok(headers)
+}
+
+/// The JSON body `--webhook-payload` selects, or None when there is nothing
+/// to send: no diff without a previous run, and an empty diff.
+pub fn body<T: Serialize>(
+    config: &Config,
+    output: &T,
+    diff: Option<&RunDiff>,
+) -> Result<Option<String>, Report> {
+    Ok(match config.webhook_payload {
+        WebhookPayload::Output => Some(serde_json::to_string(output)?),
+        WebhookPayload::Diff => match diff {
+            Some(diff) if !diff.is_empty() => Some(serde_json::to_string(diff)?),
+            _ => None,
+        },
+    })
+}
+
+/// POSTs the run's results to `--webhook-url`, if set.
+pub async fn post_results<T: Serialize>(
+    client: &Client,
+    config: &Config,
+    output: &T,
+    diff: Option<&RunDiff>,
+) -> Result<(), Report> {
+    let url = match &config.webhook_url {
+        Some(url) => url,
+        None => return Ok(()),
+    };
+    match body(config, output, diff)? {
+        Some(body) => send(client, config, url, body).await,
+        None => {
+            info!("No changes to post to the webhook");
+            Ok(())
+        }
+    }
+}
+
+/// Posts `body`, retrying connection failures, 429s and server errors up to
+/// `--webhook-retries` times with the same doubling delay as page fetches.
+async fn send(client: &Client, config: &Config, url: &str, body: String) -> Result<(), Report> {
+    let headers = headers(config)?;
+    let mut attempt = 0;
+    loop {
+        let result = client
+            .post(url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .headers(headers.clone())
+            .body(body.clone())
+            .send()
+            .await;
+        // The URL may carry a token, so errors only report the failure.
+        let error = match result {
+            Ok(response) if response.status().is_success() => {
+                info!("Posted the results to the webhook");
+                return Ok(());
+            }
+            Ok(response) => {
+                let status = response.status();
+                if !(status.is_server_error() || status.as_u16() == 429) {
+                    return Err(eyre!("The webhook rejected the results ({})", status));
+                }
+                eyre!("The webhook answered {}", status)
+            }
+            Err(err) => eyre!("Posting to the webhook failed: {}", err.without_url()),
+        };
+
+        if attempt >= config.webhook_retries {
+            return Err(error);
+        }
+        let delay = Duration::from_millis(config.backoff_ms.saturating_mul(1 << attempt.min(16)));
+        warn!("{}, retrying in {:?}", error, delay);
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BnBItem;
+
+    #[test]
+    fn builds_the_selected_payload_and_headers() {
+        let mut config = Config {
+            webhook_headers: vec![
+                "Authorization: Bearer abc".to_string(),
+                "X-Source:bnbscraper".to_string(),
+            ],
+            ..Config::default()
+        };
+        let output = serde_json::json!({"2x1": []});
+        let diff = RunDiff {
+            added: vec![BnBItem {
+                name: "Uno".to_string(),
+                ..BnBItem::default()
+            }],
+            ..RunDiff::default()
+        };
+
+        let sent = headers(&config).unwrap();
+        assert_eq!(sent["authorization"], "Bearer abc");
+        assert_eq!(sent["x-source"], "bnbscraper");
+        assert_eq!(
+            body(&config, &output, None).unwrap().as_deref(),
+            Some(r#"{"2x1":[]}"#)
+        );
+
+        config.webhook_payload = WebhookPayload::Diff;
+        assert!(body(&config, &output, Some(&diff))
+            .unwrap()
+            .unwrap()
+            .contains(r#""name":"Uno""#));
+        assert_eq!(
+            body(&config, &output, Some(&RunDiff::default())).unwrap(),
+            None
+        );
+        assert_eq!(body(&config, &output, None).unwrap(), None);
+
+        config.webhook_headers = vec!["no separator".to_string()];
+        assert!(headers(&config).is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use color_eyre::Report;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::BnBItem;
+
+/// Writes `value` as JSON next to `path` and renames it into place once it
+/// is flushed to disk, so a crash never leaves a truncated file behind.
+pub fn write_json_atomically<T: Serialize>(path: &Path, value: &T) -> Result<(), Report> {
+    write_atomically(path, |writer| Ok(serde_json::to_writer(writer, value)?))
+}
+
+/// Reads back the grouped JSON output, ordered by discount label.
+pub fn read_grouped_json(path: &Path) -> Result<BTreeMap<String, Vec<BnBItem>>, Report> {
+    Ok(serde_json::from_reader(File::open(path)?)?)
+}
+
+/// RFC 6902 JSON Patch turning `previous` into `current`, written like any
+/// other output. Returns the number of operations.
+#[cfg(feature = "patch")]
+pub fn write_json_patch(path: &Path, previous: &Value, current: &Value) -> Result<usize, Report> {
+    let patch = json_patch::diff(previous, current);
+    write_json_atomically(path, &patch)?;
+    Ok(patch.0.len())
+}
+
+#[cfg(not(feature = "patch"))]
+pub fn write_json_patch(
+    _path: &Path,
+    _previous: &Value,
+    _current: &Value,
+) -> Result<usize, Report> {
+    Err(color_eyre::eyre::eyre!(
+        "--patch needs bnbscraper built with the patch feature"
+    ))
+}
+
+/// Column layout of the CSV output.
+#[derive(Serialize)]
+struct CsvRow<'a> {
+    name: &'a str,
+    item_type: &'a str,
+    link: &'a str,
+    price: f32,
+    price_promo: f32,
+    discount: &'a str,
+    available: bool,
+    site: &'a str,
+}
+
+/// Writes one CSV row per item, with the same crash safety as the JSON output.
+pub fn write_csv_atomically(path: &Path, items: &[BnBItem]) -> Result<(), Report> {
+    write_atomically(path, |writer| {
+        let mut csv_writer = csv::Writer::from_writer(writer);
+        for item in items {
+            csv_writer.serialize(CsvRow {
+                name: &item.name,
+                item_type: &item.item_type,
+                link: &item.link,
+                price: item.price,
+                price_promo: item.price_promo,
+                discount: &item.discount,
+                available: item.available,
+                site: &item.site,
+            })?;
+        }
+        csv_writer.flush()?;
+        Ok(())
+    })
+}
+
+fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<&File>) -> Result<(), Report>,
+) -> Result<(), Report> {
+    let mut tmp_name = path.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    let tmp_path = Path::new(&tmp_name);
+
+    let file = File::create(tmp_path)?;
+    let mut writer = BufWriter::new(&file);
+    write(&mut writer)?;
+    writer.flush()?;
+    drop(writer);
+    file.sync_all()?;
+
+    fs::rename(tmp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_one_csv_row_per_item() {
+        let path = std::env::temp_dir().join(format!("bnbscraper-{}.csv", std::process::id()));
+        let items = vec![BnBItem {
+            name: "Vela, edición limitada".to_string(),
+            item_type: "Vela de 3 mechas".to_string(),
+            link: "/velas/edicion-limitada".to_string(),
+            price: 650.0,
+            price_promo: 455.0,
+            price_with_tax: 455.0,
+            discount: "30% de descuento".to_string(),
+            image_url: String::new(),
+            available: true,
+            site: "mx".to_string(),
+            details: None,
+            provenance: None,
+        }];
+
+        write_csv_atomically(&path, &items).unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            written,
+            "name,item_type,link,price,price_promo,discount,available,site\n\
+             \"Vela, edición limitada\",Vela de 3 mechas,/velas/edicion-limitada,650.0,455.0,30% de descuento,true,mx\n"
+        );
+    }
+
+    #[cfg(feature = "patch")]
+    #[test]
+    fn patch_applies_to_the_previous_output() {
+        let path = std::env::temp_dir().join(format!("bnbscraper-{}.patch", std::process::id()));
+        let mut previous = serde_json::json!({"2x1": [{"name": "Uno", "price": 100.0}]});
+        let current = serde_json::json!({
+            "2x1": [{"name": "Uno", "price": 90.0}],
+            "": [{"name": "Dos", "price": 50.0}],
+        });
+
+        let operations = write_json_patch(&path, &previous, &current).unwrap();
+        let patch: json_patch::Patch =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        json_patch::patch(&mut previous, &patch).unwrap();
+        assert_eq!(operations, 2);
+        assert_eq!(previous, current);
+    }
+}
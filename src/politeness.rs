@@ -0,0 +1,209 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use color_eyre::Report;
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::output::write_json_atomically;
+use crate::robots::Robots;
+
+/// One request the scraper sent to a site.
+#[derive(Debug, Clone, Serialize)]
+pub struct RequestRecord {
+    pub url: String,
+    /// Milliseconds since the Unix epoch when the request was sent.
+    pub sent_at_ms: u64,
+    /// Size of the decoded body; zero for failed requests.
+    pub bytes: usize,
+    pub ok: bool,
+    /// Whether this was a retry of a failed attempt.
+    pub retry: bool,
+    /// Whether the site's robots.txt allows the URL. Unchecked under
+    /// `--ignore-robots`.
+    pub robots_allowed: Option<bool>,
+}
+
+/// Requests one [`crate::Scraper`] has sent so far. Shared by its concurrent
+/// fetches.
+#[derive(Debug, Default)]
+pub struct RequestLog {
+    records: Mutex<Vec<RequestRecord>>,
+}
+
+impl RequestLog {
+    pub fn record(&self, url: &str, bytes: usize, ok: bool, retry: bool) {
+        let sent_at_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default();
+        self.records.lock().unwrap().push(RequestRecord {
+            url: url.to_string(),
+            sent_at_ms,
+            bytes,
+            ok,
+            retry,
+            robots_allowed: None,
+        });
+    }
+
+    /// Marks every request so far against the site's robots rules.
+    pub fn check_robots(&self, robots: &Robots) {
+        for record in self.records.lock().unwrap().iter_mut() {
+            record.robots_allowed = Some(robots.allows(&record.url));
+        }
+    }
+
+    pub fn take(&self) -> Vec<RequestRecord> {
+        std::mem::take(&mut self.records.lock().unwrap())
+    }
+}
+
+/// How a run treated the sites it crawled, the numbers to show a site
+/// operator who asks what the scraper did.
+#[derive(Debug, Serialize)]
+pub struct PolitenessReport {
+    pub user_agent: &'static str,
+    pub first_request_at_ms: u64,
+    pub duration_secs: f64,
+    pub total_requests: usize,
+    pub failed_requests: usize,
+    pub retries: usize,
+    pub bytes_transferred: usize,
+    /// Responses served without contacting the site. The scraper keeps no
+    /// response cache, so every request reaches the site.
+    pub cache_hits: usize,
+    pub cache_hit_ratio: f64,
+    /// Requests started in each minute of the run, from the first request.
+    pub requests_per_minute: Vec<usize>,
+    pub peak_requests_per_second: usize,
+    pub max_concurrency: usize,
+    pub min_delay_ms: u64,
+    pub requests_per_second_limit: Option<f64>,
+    pub robots: RobotsCompliance,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RobotsCompliance {
+    pub honored: bool,
+    /// Discovered links skipped because robots.txt disallows them.
+    pub disallowed_links_skipped: usize,
+    /// Requests robots.txt disallows that were sent anyway.
+    pub disallowed_requests: usize,
+    /// Largest gap between two requests to a host that robots.txt's
+    /// Crawl-delay or the settings imposed.
+    pub enforced_interval_ms: u64,
+}
+
+/// What a crawl recorded for its politeness report.
+#[derive(Debug, Default)]
+pub struct CrawlLog {
+    pub requests: Vec<RequestRecord>,
+    pub disallowed_links_skipped: usize,
+    pub enforced_interval: Duration,
+}
+
+impl CrawlLog {
+    pub fn absorb(&mut self, other: CrawlLog) {
+        self.requests.extend(other.requests);
+        self.disallowed_links_skipped += other.disallowed_links_skipped;
+        self.enforced_interval = self.enforced_interval.max(other.enforced_interval);
+    }
+
+    pub fn report(&self, config: &Config) -> PolitenessReport {
+        let first = self
+            .requests
+            .iter()
+            .map(|request| request.sent_at_ms)
+            .min()
+            .unwrap_or_default();
+        let last = self
+            .requests
+            .iter()
+            .map(|request| request.sent_at_ms)
+            .max()
+            .unwrap_or_default();
+
+        let mut per_minute = vec![0; ((last - first) / 60_000) as usize + 1];
+        let mut per_second: BTreeMap<u64, usize> = BTreeMap::new();
+        for request in &self.requests {
+            per_minute[((request.sent_at_ms - first) / 60_000) as usize] += 1;
+            *per_second.entry(request.sent_at_ms / 1000).or_default() += 1;
+        }
+        if self.requests.is_empty() {
+            per_minute.clear();
+        }
+
+        PolitenessReport {
+            user_agent: concat!("bnbscraper/", env!("CARGO_PKG_VERSION")),
+            first_request_at_ms: first,
+            duration_secs: (last - first) as f64 / 1000.0,
+            total_requests: self.requests.len(),
+            failed_requests: self.requests.iter().filter(|request| !request.ok).count(),
+            retries: self.requests.iter().filter(|request| request.retry).count(),
+            bytes_transferred: self.requests.iter().map(|request| request.bytes).sum(),
+            cache_hits: 0,
+            cache_hit_ratio: 0.0,
+            requests_per_minute: per_minute,
+            peak_requests_per_second: per_second.values().copied().max().unwrap_or_default(),
+            max_concurrency: config.max_concurrency,
+            min_delay_ms: config.min_delay_ms,
+            requests_per_second_limit: config.requests_per_second,
+            robots: RobotsCompliance {
+                honored: !config.ignore_robots,
+                disallowed_links_skipped: self.disallowed_links_skipped,
+                disallowed_requests: self
+                    .requests
+                    .iter()
+                    .filter(|request| request.robots_allowed == Some(false))
+                    .count(),
+                enforced_interval_ms: self.enforced_interval.as_millis() as u64,
+            },
+        }
+    }
+
+    pub fn write_report(&self, path: &Path, config: &Config) -> Result<(), Report> {
+        write_json_atomically(path, &self.report(config))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_requests_and_robots_compliance() {
+        let request = |url: &str, sent_at_ms: u64, ok: bool| RequestRecord {
+            url: url.to_string(),
+            sent_at_ms,
+            bytes: if ok { 1000 } else { 0 },
+            ok,
+            retry: false,
+            robots_allowed: Some(!url.contains("/checkout")),
+        };
+        let log = CrawlLog {
+            requests: vec![
+                request("https://www.bathandbodyworks.mx/", 1_000, true),
+                request("https://www.bathandbodyworks.mx/velas", 1_500, false),
+                RequestRecord {
+                    retry: true,
+                    ..request("https://www.bathandbodyworks.mx/velas", 2_000, true)
+                },
+                request("https://www.bathandbodyworks.mx/checkout", 125_000, true),
+            ],
+            disallowed_links_skipped: 2,
+            enforced_interval: Duration::from_secs(1),
+        };
+
+        let report = log.report(&Config::default());
+        assert_eq!(report.total_requests, 4);
+        assert_eq!((report.failed_requests, report.retries), (1, 1));
+        assert_eq!(report.bytes_transferred, 3000);
+        assert_eq!(report.requests_per_minute, vec![3, 0, 1]);
+        assert_eq!(report.peak_requests_per_second, 2);
+        assert_eq!(report.robots.disallowed_requests, 1);
+        assert_eq!(report.robots.enforced_interval_ms, 1000);
+    }
+}
@@ -0,0 +1,183 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::eyre;
+use color_eyre::Report;
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::manifest::{Artifact, Manifest};
+use crate::output::{read_grouped_json, write_json_atomically};
+use crate::{signing, unix_timestamp};
+
+/// Contents of `latest.json`, the pointer consumers read first.
+#[derive(Serialize)]
+struct Latest<'a> {
+    version: &'a str,
+    published_at: u64,
+    manifest: String,
+}
+
+/// Rejects outputs a consumer shouldn't be handed: unreadable, empty, or
+/// with nameless items.
+pub fn validate(output: &Path) -> Result<usize, Report> {
+    let grouped = read_grouped_json(output)?;
+    let items: Vec<_> = grouped.values().flatten().collect();
+    if items.is_empty() {
+        return Err(eyre!("{:?} has no items", output));
+    }
+    if let Some(item) = items.iter().find(|item| item.name.trim().is_empty()) {
+        return Err(eyre!(
+            "{:?} has an item without a name: {:?}",
+            output,
+            item.link
+        ));
+    }
+    Ok(items.len())
+}
+
+/// Publishes the latest JSON output into `dest` as a new version directory,
+/// then points `dest/latest.json` at it. Each step only touches a staging
+/// directory until the final renames, so a failure leaves the previously
+/// published version in place.
+pub fn publish(config: &Config, dest: &Path) -> Result<PathBuf, Report> {
+    let version = free_version(dest);
+    let staging = dest.join(format!(".staging-{}", version));
+    let published = dest.join(&version);
+
+    let mut renamed = false;
+    let result = stage(config, &staging).and_then(|()| {
+        fs::rename(&staging, &published)?;
+        renamed = true;
+        point_latest(dest, &version)
+    });
+
+    match result {
+        Ok(()) => {
+            info!("Published version {} to {:?}", version, dest);
+            Ok(published)
+        }
+        Err(err) => {
+            warn!("Publishing failed, rolling back: {}", err);
+            let created = if renamed { &published } else { &staging };
+            if created.exists() {
+                fs::remove_dir_all(created)?;
+            }
+            Err(err)
+        }
+    }
+}
+
+/// The current timestamp, suffixed when a version was already published
+/// within the same second.
+fn free_version(dest: &Path) -> String {
+    let timestamp = unix_timestamp();
+    let mut version = timestamp.to_string();
+    let mut suffix = 1;
+    while dest.join(&version).exists() {
+        version = format!("{}-{}", timestamp, suffix);
+        suffix += 1;
+    }
+    version
+}
+
+/// Export, validate and checksum into `staging`.
+fn stage(config: &Config, staging: &Path) -> Result<(), Report> {
+    let output = config.require_json_output()?;
+    let items = validate(&output)?;
+    fs::create_dir_all(staging)?;
+
+    let data = staging.join("data.json");
+    fs::copy(&output, &data)?;
+    signing::write_checksum(&data)?;
+
+    let mut artifact = Artifact::describe(&data, "output")?;
+    artifact.path = PathBuf::from("data.json");
+    let manifest_path = staging.join("manifest.json");
+    Manifest::new(vec![artifact]).save(&manifest_path)?;
+    signing::write_checksum(&manifest_path)?;
+    #[cfg(feature = "signing")]
+    if let Some(key) = &config.signing_key {
+        signing::sign_file(&manifest_path, &signing::load_signing_key(key)?)?;
+    }
+
+    info!("Staged {} items in {:?}", items, staging);
+    Ok(())
+}
+
+/// Deletes all but the `keep` newest versions in `dest`, never the one
+/// `latest.json` points at. Returns the number deleted.
+pub fn rotate_versions(dest: &Path, keep: usize) -> Result<usize, Report> {
+    let latest: Option<String> = fs::read_to_string(dest.join("latest.json"))
+        .ok()
+        .and_then(|text| serde_json::from_str::<serde_json::Value>(&text).ok())
+        .and_then(|latest| latest["version"].as_str().map(str::to_string));
+
+    // Versions are timestamps, optionally suffixed; staging dirs start with a dot.
+    let mut versions: Vec<(u64, String)> = vec![];
+    for entry in fs::read_dir(dest)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let timestamp = name.split('-').next().and_then(|stamp| stamp.parse().ok());
+        if let (Some(timestamp), true) = (timestamp, entry.file_type()?.is_dir()) {
+            versions.push((timestamp, name));
+        }
+    }
+    versions.sort();
+
+    let mut deleted = 0;
+    let expired = versions.len().saturating_sub(keep);
+    for (_, version) in versions.into_iter().take(expired) {
+        if latest.as_deref() == Some(version.as_str()) {
+            continue;
+        }
+        fs::remove_dir_all(dest.join(&version))?;
+        deleted += 1;
+    }
+    Ok(deleted)
+}
+
+fn point_latest(dest: &Path, version: &str) -> Result<(), Report> {
+    write_json_atomically(
+        &dest.join("latest.json"),
+        &Latest {
+            version,
+            published_at: unix_timestamp(),
+            manifest: format!("{}/manifest.json", version),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_validation_leaves_latest_untouched() {
+        let dest = std::env::temp_dir().join(format!("bnbscraper-publish-{}", std::process::id()));
+        fs::create_dir_all(&dest).unwrap();
+        let output = dest.join("output.json");
+        let config = Config {
+            output: output.clone(),
+            ..Config::default()
+        };
+
+        fs::write(&output, r#"{"2x1": [{"name": "Uno", "item_type": "", "link": "/uno", "price": 1.0, "price_promo": 0.0, "price_with_tax": 1.0, "discount": "2x1"}]}"#).unwrap();
+        let first = publish(&config, &dest).unwrap();
+        let checksummed = first.join("data.json.sha256").exists();
+        let latest = fs::read_to_string(dest.join("latest.json")).unwrap();
+
+        fs::write(&output, "{}").unwrap();
+        let second = publish(&config, &dest);
+        let latest_after = fs::read_to_string(dest.join("latest.json")).unwrap();
+        let entries = fs::read_dir(&dest).unwrap().count();
+        fs::remove_dir_all(&dest).unwrap();
+
+        assert!(checksummed);
+        assert!(second.is_err());
+        assert_eq!(latest, latest_after);
+        // output.json, latest.json and the one published version
+        assert_eq!(entries, 3);
+    }
+}
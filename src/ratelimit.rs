@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use reqwest::Url;
+use tokio::sync::Mutex;
+use tokio::time::{sleep_until, Instant};
+
+use crate::config::Config;
+
+/// Spaces requests to the same host at least `interval` apart, however many
+/// fetches are in flight. Each caller reserves the next free slot for its
+/// host and sleeps until then, so waiting never holds the lock.
+pub struct HostRateLimiter {
+    interval_ms: AtomicU64,
+    next_slot: Mutex<HashMap<String, Instant>>,
+}
+
+impl HostRateLimiter {
+    pub fn new(interval: Duration) -> Self {
+        HostRateLimiter {
+            interval_ms: AtomicU64::new(interval.as_millis() as u64),
+            next_slot: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The stricter of `--min-delay-ms` and `--requests-per-second`.
+    pub fn from_config(config: &Config) -> Self {
+        let from_rate = config
+            .requests_per_second
+            .filter(|rate| *rate > 0.0)
+            .map(|rate| Duration::from_secs_f64(1.0 / rate))
+            .unwrap_or_default();
+        HostRateLimiter::new(from_rate.max(Duration::from_millis(config.min_delay_ms)))
+    }
+
+    /// Lengthens the interval to at least `interval`, e.g. for a site's
+    /// robots.txt Crawl-delay.
+    pub fn raise_interval(&self, interval: Duration) {
+        self.interval_ms
+            .fetch_max(interval.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval_ms.load(Ordering::Relaxed))
+    }
+
+    /// Waits until a request to the host of `url` is allowed.
+    pub async fn wait(&self, url: &str) {
+        let interval = self.interval();
+        if interval.is_zero() {
+            return;
+        }
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_default();
+
+        let slot = {
+            let mut next_slot = self.next_slot.lock().await;
+            let now = Instant::now();
+            let slot = next_slot.get(&host).copied().unwrap_or(now).max(now);
+            next_slot.insert(host, slot + interval);
+            slot
+        };
+        sleep_until(slot).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn spaces_requests_per_host() {
+        let limiter = HostRateLimiter::new(Duration::from_millis(50));
+        let started = Instant::now();
+
+        for _ in 0..3 {
+            limiter.wait("https://www.bathandbodyworks.mx/velas").await;
+        }
+        limiter.wait("https://cdn.example.com/a.png").await;
+
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(100), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(150), "{:?}", elapsed);
+    }
+}
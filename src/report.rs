@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use clap::ValueEnum;
+use color_eyre::Report;
+use fluent_bundle::FluentValue;
+use prettytable::{Cell, Row, Table};
+
+use crate::i18n::Localizer;
+use crate::output::read_grouped_json;
+use crate::BnBItem;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ReportStyle {
+    /// Aligned table, one row per item
+    Table,
+    /// Linear labelled sentences with no layout, for screen readers and TTS
+    Plain,
+}
+
+fn price_text(price: f32) -> String {
+    format!("{:.2}", price)
+}
+
+fn discount_label(discount: &str, localizer: &Localizer) -> String {
+    if discount.is_empty() {
+        localizer.text("report-no-discount", &[])
+    } else {
+        discount.to_string()
+    }
+}
+
+pub fn render(
+    grouped: &BTreeMap<String, Vec<BnBItem>>,
+    style: ReportStyle,
+    localizer: &Localizer,
+) -> String {
+    match style {
+        ReportStyle::Table => render_table(grouped, localizer),
+        ReportStyle::Plain => render_plain(grouped, localizer),
+    }
+}
+
+fn render_table(grouped: &BTreeMap<String, Vec<BnBItem>>, localizer: &Localizer) -> String {
+    let mut table = Table::new();
+    table.set_titles(Row::new(
+        [
+            "report-column-discount",
+            "report-column-name",
+            "report-column-type",
+            "report-column-price",
+            "report-column-promo",
+        ]
+        .iter()
+        .map(|id| Cell::new(&localizer.text(id, &[])))
+        .collect(),
+    ));
+
+    for (discount, items) in grouped {
+        for item in items {
+            table.add_row(Row::new(vec![
+                Cell::new(&discount_label(discount, localizer)),
+                Cell::new(&item.name),
+                Cell::new(&item.item_type),
+                Cell::new(&price_text(item.price)),
+                Cell::new(&price_text(item.price_promo)),
+            ]));
+        }
+    }
+
+    table.to_string()
+}
+
+fn render_plain(grouped: &BTreeMap<String, Vec<BnBItem>>, localizer: &Localizer) -> String {
+    let mut lines = vec![];
+
+    for (discount, items) in grouped {
+        lines.push(localizer.text(
+            "report-plain-group",
+            &[
+                ("discount", discount_label(discount, localizer).into()),
+                ("count", FluentValue::from(items.len())),
+            ],
+        ));
+        for (position, item) in items.iter().enumerate() {
+            lines.push(localizer.text(
+                "report-plain-item",
+                &[
+                    ("position", FluentValue::from(position + 1)),
+                    ("count", FluentValue::from(items.len())),
+                    ("name", item.name.as_str().into()),
+                    ("type", item.item_type.as_str().into()),
+                    ("price", price_text(item.price).into()),
+                    ("promo", price_text(item.price_promo).into()),
+                    ("link", item.link.as_str().into()),
+                ],
+            ));
+        }
+        lines.push(String::new());
+    }
+
+    lines.join("\n")
+}
+
+/// Prints the latest grouped JSON output in the requested style.
+pub fn run(json_file: &Path, style: ReportStyle, localizer: &Localizer) -> Result<(), Report> {
+    let grouped = read_grouped_json(json_file)?;
+    println!("{}", render(&grouped, style, localizer));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i18n::Lang;
+
+    #[test]
+    fn plain_report_is_linear_and_labelled() {
+        let mut grouped = BTreeMap::new();
+        grouped.insert(
+            "2x1".to_string(),
+            vec![BnBItem {
+                name: "Producto Tres".to_string(),
+                item_type: "Jabón de manos".to_string(),
+                link: "/jabones/producto-tres".to_string(),
+                price: 219.0,
+                price_promo: 109.5,
+                ..BnBItem::default()
+            }],
+        );
+
+        let report = render(&grouped, ReportStyle::Plain, &Localizer::new(Lang::EsMx));
+        assert_eq!(
+            report.lines().collect::<Vec<_>>(),
+            vec![
+                "Descuento: 2x1. 1 productos.",
+                "Producto 1 de 1: Producto Tres. Tipo: Jabón de manos. Precio original: 219.00 pesos. \
+                 Precio con descuento: 109.50 pesos. Enlace: /jabones/producto-tres.",
+            ]
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use clap::Subcommand;
+use color_eyre::eyre::eyre;
+use color_eyre::Report;
+use fluent_bundle::FluentValue;
+
+use crate::config::Config;
+use crate::i18n::Localizer;
+use crate::store::SqliteStore;
+
+/// What to do with the prices `--review-factor` held back.
+#[derive(Subcommand, Debug)]
+pub enum ReviewAction {
+    /// Print the prices waiting for review
+    List,
+    /// Record prices in the history as the site really listed them
+    Accept {
+        /// Review ids, as printed by `review list`
+        #[arg(required_unless_present = "all")]
+        ids: Vec<i64>,
+        /// Every queued price
+        #[arg(long, conflicts_with = "ids")]
+        all: bool,
+    },
+    /// Drop prices that were scrape glitches
+    Reject {
+        /// Review ids, as printed by `review list`
+        #[arg(required_unless_present = "all")]
+        ids: Vec<i64>,
+        /// Every queued price
+        #[arg(long, conflicts_with = "ids")]
+        all: bool,
+    },
+}
+
+pub fn run(config: &Config, action: ReviewAction, localizer: &Localizer) -> Result<(), Report> {
+    let url = config
+        .store
+        .as_deref()
+        .ok_or_else(|| eyre!("Reviewing prices needs a --store"))?;
+    let mut store = SqliteStore::open(url)?;
+    let pending = store.pending_reviews()?;
+
+    let (accept, ids, all) = match action {
+        ReviewAction::List => {
+            if pending.is_empty() {
+                println!("{}", localizer.text("review-empty", &[]));
+            }
+            for review in &pending {
+                let price = if review.price_promo > 0.0 {
+                    review.price_promo
+                } else {
+                    review.price
+                };
+                let factor = if review.previous_price > 0.0 {
+                    price / review.previous_price
+                } else {
+                    0.0
+                };
+                println!(
+                    "{}",
+                    localizer.text(
+                        "review-item",
+                        &[
+                            ("id", FluentValue::from(review.id)),
+                            ("name", review.name.as_str().into()),
+                            ("previous", format!("{:.2}", review.previous_price).into()),
+                            ("price", format!("{:.2}", price).into()),
+                            ("factor", format!("{:.1}", factor).into()),
+                            ("link", review.link.as_str().into()),
+                        ],
+                    )
+                );
+            }
+            return Ok(());
+        }
+        ReviewAction::Accept { ids, all } => (true, ids, all),
+        ReviewAction::Reject { ids, all } => (false, ids, all),
+    };
+
+    let ids = if all {
+        pending.iter().map(|review| review.id).collect()
+    } else {
+        ids
+    };
+    let mut resolved = 0;
+    for id in ids {
+        if store.resolve_review(id, accept)? {
+            resolved += 1;
+        } else {
+            return Err(eyre!("No price #{} is waiting for review", id));
+        }
+    }
+    let message = if accept {
+        "review-accepted"
+    } else {
+        "review-rejected"
+    };
+    println!(
+        "{}",
+        localizer.text(message, &[("count", FluentValue::from(resolved))])
+    );
+    Ok(())
+}
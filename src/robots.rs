@@ -0,0 +1,171 @@
+use std::fmt;
+use std::time::Duration;
+
+use reqwest::Url;
+
+// Token matched against robots.txt User-agent lines, besides `*`.
+const AGENT: &str = "bnbscraper";
+
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+impl Rule {
+    /// Robots patterns match path prefixes, with `*` matching any run of
+    /// characters and a trailing `$` anchoring the end.
+    fn matches(&self, path: &str) -> bool {
+        let (pattern, anchored) = match self.pattern.strip_suffix('$') {
+            Some(pattern) => (pattern, true),
+            None => (self.pattern.as_str(), false),
+        };
+
+        let parts: Vec<&str> = pattern.split('*').collect();
+        let mut position = 0;
+        for (i, part) in parts.iter().enumerate() {
+            let rest = &path[position..];
+            if i == 0 {
+                if !rest.starts_with(part) {
+                    return false;
+                }
+                position += part.len();
+            } else if anchored && i == parts.len() - 1 {
+                return rest.ends_with(part);
+            } else {
+                match rest.find(part) {
+                    Some(at) => position += at + part.len(),
+                    None => return false,
+                }
+            }
+        }
+        !anchored || position == path.len()
+    }
+}
+
+/// A URL the site's robots.txt keeps this scraper from requesting.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Disallowed(pub String);
+
+impl fmt::Display for Disallowed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} is disallowed by robots.txt", self.0)
+    }
+}
+
+impl std::error::Error for Disallowed {}
+
+/// The rules of a robots.txt that apply to this scraper.
+#[derive(Debug, Default, PartialEq)]
+pub struct Robots {
+    rules: Vec<Rule>,
+    pub crawl_delay: Option<Duration>,
+}
+
+impl Robots {
+    /// Parses a robots.txt, keeping the group naming this scraper or, when
+    /// there is none, the `*` group. Unknown lines are ignored, so an HTML
+    /// error page parses as "everything allowed".
+    pub fn parse(text: &str) -> Self {
+        let mut specific = Robots::default();
+        let mut wildcard = Robots::default();
+        let mut found_specific = false;
+
+        let mut agents: Vec<String> = vec![];
+        let mut in_rules = false;
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key.trim().to_ascii_lowercase(), value.trim()),
+                None => continue,
+            };
+
+            if key == "user-agent" {
+                if in_rules {
+                    agents.clear();
+                    in_rules = false;
+                }
+                agents.push(value.to_ascii_lowercase());
+                continue;
+            }
+            in_rules = true;
+
+            let mut targets = vec![];
+            if agents
+                .iter()
+                .any(|agent| AGENT.contains(agent.as_str()) && agent != "*")
+            {
+                found_specific = true;
+                targets.push(&mut specific);
+            } else if agents.iter().any(|agent| agent == "*") {
+                targets.push(&mut wildcard);
+            }
+
+            for robots in targets {
+                match key.as_str() {
+                    "allow" | "disallow" if !value.is_empty() => robots.rules.push(Rule {
+                        allow: key == "allow",
+                        pattern: value.to_string(),
+                    }),
+                    "crawl-delay" => {
+                        robots.crawl_delay = value.parse::<f64>().ok().map(Duration::from_secs_f64)
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        if found_specific {
+            specific
+        } else {
+            wildcard
+        }
+    }
+
+    /// The longest matching rule decides; on a tie, Allow wins.
+    pub fn allows(&self, url: &str) -> bool {
+        let path = match Url::parse(url) {
+            Ok(url) => match url.query() {
+                Some(query) => format!("{}?{}", url.path(), query),
+                None => url.path().to_string(),
+            },
+            Err(_) => return true,
+        };
+
+        self.rules
+            .iter()
+            .filter(|rule| rule.matches(&path))
+            .max_by_key(|rule| (rule.pattern.len(), rule.allow))
+            .is_none_or(|rule| rule.allow)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_the_matching_group_and_crawl_delay() {
+        let robots = Robots::parse(
+            "User-agent: Googlebot\n\
+             Disallow: /\n\
+             \n\
+             User-agent: *\n\
+             Crawl-delay: 2\n\
+             Disallow: /checkout/\n\
+             Disallow: /*?price=\n\
+             Disallow: /*.pdf$\n\
+             Allow: /checkout/cart-promo\n",
+        );
+        let url = |path: &str| format!("https://www.bathandbodyworks.mx{}", path);
+
+        assert_eq!(robots.crawl_delay, Some(Duration::from_secs(2)));
+        assert!(robots.allows(&url("/velas")));
+        assert!(!robots.allows(&url("/checkout/onepage")));
+        assert!(robots.allows(&url("/checkout/cart-promo")));
+        assert!(!robots.allows(&url("/velas?price=100-200")));
+        assert!(!robots.allows(&url("/catalogo.pdf")));
+        assert!(robots.allows(&url("/catalogo.pdf.html")));
+        assert_eq!(Robots::parse("<html>Not found</html>"), Robots::default());
+    }
+}
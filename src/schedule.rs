@@ -0,0 +1,301 @@
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use color_eyre::eyre::eyre;
+use color_eyre::Report;
+use tracing::{info, warn};
+
+use crate::interrupt::Interrupt;
+use crate::unix_timestamp;
+
+const MINUTE: u64 = 60;
+const DAY: u64 = 24 * 60 * MINUTE;
+// A cron expression that matches nothing within this many days never will
+// (e.g. February 30th).
+const MAX_CRON_DAYS: u64 = 5 * 366;
+
+/// Parses an interval such as `6h`, `90m` or `1h30m`; the units are `d`,
+/// `h`, `m` and `s`.
+pub fn parse_interval(text: &str) -> Result<Duration, String> {
+    let mut seconds = 0;
+    let mut digits = String::new();
+    for c in text.trim().chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let unit = match c {
+            'd' => DAY,
+            'h' => 60 * MINUTE,
+            'm' => MINUTE,
+            's' => 1,
+            _ => return Err(format!("unknown unit {:?} in interval {:?}", c, text)),
+        };
+        let count: u64 = digits
+            .parse()
+            .map_err(|_| format!("missing number before {:?} in interval {:?}", c, text))?;
+        seconds += count * unit;
+        digits.clear();
+    }
+    if !digits.is_empty() {
+        return Err(format!(
+            "interval {:?} needs a unit, e.g. {}m",
+            text, digits
+        ));
+    }
+    if seconds == 0 {
+        return Err(format!("interval {:?} must be longer than zero", text));
+    }
+    Ok(Duration::from_secs(seconds))
+}
+
+/// The allowed values of one cron field, as bits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct CronField {
+    bits: u64,
+    /// Written as `*`, which matters for the day-of-month/day-of-week rule.
+    any: bool,
+}
+
+impl CronField {
+    fn parse(text: &str, min: u32, max: u32) -> Result<Self, Report> {
+        let mut bits = 0;
+        for item in text.split(',') {
+            let (range, step) = match item.split_once('/') {
+                Some((range, step)) => (range, step.parse::<u32>().ok().filter(|step| *step > 0)),
+                None => (item, Some(1)),
+            };
+            let step = step.ok_or_else(|| eyre!("Invalid step in cron field {:?}", text))?;
+            let number = |value: &str| {
+                value
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|value| (min..=max).contains(value))
+                    .ok_or_else(|| {
+                        eyre!(
+                            "{:?} is outside {}-{} in cron field {:?}",
+                            value,
+                            min,
+                            max,
+                            text
+                        )
+                    })
+            };
+            let (start, end) = match range.split_once('-') {
+                _ if range == "*" => (min, max),
+                Some((start, end)) => (number(start)?, number(end)?),
+                // `5/15` means from 5 to the end, every 15.
+                None if item.contains('/') => (number(range)?, max),
+                None => {
+                    let value = number(range)?;
+                    (value, value)
+                }
+            };
+            for value in (start..=end).step_by(step as usize) {
+                bits |= 1 << value;
+            }
+        }
+        Ok(CronField {
+            bits,
+            any: text == "*",
+        })
+    }
+
+    fn matches(&self, value: u32) -> bool {
+        self.bits & (1 << value) != 0
+    }
+}
+
+/// A five-field cron expression (minute, hour, day of month, month, day of
+/// week), evaluated in UTC. As in cron, when both day fields are restricted
+/// a day matching either one runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cron {
+    minute: CronField,
+    hour: CronField,
+    day: CronField,
+    month: CronField,
+    weekday: CronField,
+}
+
+impl Cron {
+    pub fn parse(text: &str) -> Result<Self, Report> {
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(eyre!(
+                "Cron expression {:?} needs 5 fields: minute hour day month weekday",
+                text
+            ));
+        }
+        let mut weekday = CronField::parse(fields[4], 0, 7)?;
+        // Both 0 and 7 are Sunday.
+        if weekday.matches(7) {
+            weekday.bits |= 1;
+        }
+        Ok(Cron {
+            minute: CronField::parse(fields[0], 0, 59)?,
+            hour: CronField::parse(fields[1], 0, 23)?,
+            day: CronField::parse(fields[2], 1, 31)?,
+            month: CronField::parse(fields[3], 1, 12)?,
+            weekday,
+        })
+    }
+
+    fn matches_day(&self, days: u64) -> bool {
+        let (_, month, day) = civil_from_days(days);
+        // 1970-01-01 was a Thursday.
+        let weekday = ((days + 4) % 7) as u32;
+        let by_day = match (self.day.any, self.weekday.any) {
+            (false, false) => self.day.matches(day) || self.weekday.matches(weekday),
+            _ => self.day.matches(day) && self.weekday.matches(weekday),
+        };
+        self.month.matches(month) && by_day
+    }
+
+    /// The first matching minute strictly after `timestamp`, in Unix seconds.
+    pub fn next_after(&self, timestamp: u64) -> Option<u64> {
+        let start = (timestamp / MINUTE + 1) * MINUTE;
+        let first_day = start / DAY;
+        for days in first_day..first_day + MAX_CRON_DAYS {
+            if !self.matches_day(days) {
+                continue;
+            }
+            for hour in (0..24).filter(|hour| self.hour.matches(*hour)) {
+                for minute in (0..60).filter(|minute| self.minute.matches(*minute)) {
+                    let candidate = days * DAY + u64::from(hour * 60 + minute) * MINUTE;
+                    if candidate >= start {
+                        return Some(candidate);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Year, month and day of a number of days since 1970-01-01.
+fn civil_from_days(days: u64) -> (u64, u32, u32) {
+    let shifted = days + 719_468;
+    let era = shifted / 146_097;
+    let day_of_era = shifted - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// When the daemon runs a scrape.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schedule {
+    /// Each cycle starts this long after the previous one started, or right
+    /// after it ends if it took longer.
+    Every(Duration),
+    Cron(Cron),
+}
+
+impl Schedule {
+    /// How long to wait before the next cycle, given when the last one started.
+    fn wait(&self, cycle_started: Instant) -> Result<Duration, Report> {
+        match self {
+            Schedule::Every(interval) => Ok(interval.saturating_sub(cycle_started.elapsed())),
+            Schedule::Cron(cron) => {
+                let now = unix_timestamp();
+                let next = cron
+                    .next_after(now)
+                    .ok_or_else(|| eyre!("The cron expression never matches"))?;
+                Ok(Duration::from_secs(next - now))
+            }
+        }
+    }
+}
+
+/// Runs `cycle` on `schedule` until `interrupt` is set. A failed cycle is
+/// logged and the next one runs as planned, so a site outage or network
+/// blip doesn't end the daemon.
+pub async fn run_daemon<F, Fut>(
+    schedule: &Schedule,
+    interrupt: &Interrupt,
+    mut cycle: F,
+) -> Result<(), Report>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<(), Report>>,
+{
+    // A cron schedule waits for its first slot; an interval starts right away.
+    if let Schedule::Cron(_) = schedule {
+        let wait = schedule.wait(Instant::now())?;
+        info!("First scrape in {:?}", wait);
+        if !sleep_unless_interrupted(wait, interrupt).await {
+            return Ok(());
+        }
+    }
+
+    let mut failures = 0;
+    loop {
+        let started = Instant::now();
+        match cycle().await {
+            Ok(()) => failures = 0,
+            Err(err) => {
+                failures += 1;
+                warn!("Scrape failed ({} in a row): {:#}", failures, err);
+            }
+        }
+        if interrupt.is_set() {
+            return Ok(());
+        }
+        let wait = schedule.wait(started)?;
+        info!("Next scrape in {:?}", wait);
+        if !sleep_unless_interrupted(wait, interrupt).await {
+            return Ok(());
+        }
+    }
+}
+
+/// Sleeps for `wait`; false when the interrupt cut it short.
+async fn sleep_unless_interrupted(wait: Duration, interrupt: &Interrupt) -> bool {
+    tokio::select! {
+        _ = tokio::time::sleep(wait) => true,
+        _ = interrupt.wait() => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_intervals() {
+        assert_eq!(parse_interval("6h"), Ok(Duration::from_secs(6 * 3600)));
+        assert_eq!(parse_interval("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_interval("1d"), Ok(Duration::from_secs(86_400)));
+        assert!(parse_interval("90").is_err());
+        assert!(parse_interval("0m").is_err());
+        assert!(parse_interval("2w").is_err());
+    }
+
+    #[test]
+    fn finds_the_next_cron_slot() {
+        // 2024-02-28 23:59:30 UTC, a Wednesday.
+        let now = 1_709_164_770;
+        let at = |expression: &str| Cron::parse(expression).unwrap().next_after(now);
+
+        assert_eq!(at("* * * * *"), Some(1_709_164_800));
+        // 06:00 on 2024-02-29, the leap day.
+        assert_eq!(at("0 6-23/6 * * *"), Some(1_709_186_400));
+        // The first of the month or a Sunday, whichever comes first: Friday the 1st.
+        assert_eq!(at("30 8 1 * 0"), Some(1_709_281_800));
+        // Sunday written as 7.
+        assert_eq!(at("0 0 * * 7"), Some(1_709_424_000));
+        assert_eq!(at("0 0 30 2 *"), None);
+        assert!(Cron::parse("0 0 * *").is_err());
+        assert!(Cron::parse("61 * * * *").is_err());
+    }
+}
@@ -0,0 +1,1768 @@
+use std::any::Any;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use color_eyre::eyre::eyre;
+use color_eyre::Report;
+use fluent_bundle::FluentValue;
+use futures::{future, stream, FutureExt, StreamExt};
+use rand::Rng;
+use reqwest::Url;
+use select::document::Document;
+use select::predicate::Name;
+use serde_json::Value;
+use tokio::sync::OnceCell;
+use tokio::time::timeout_at;
+use tracing::{info, warn};
+
+use crate::budget::{by_detail_priority, RunBudget};
+use crate::buffer::ItemBuffer;
+use crate::checkpoint::Checkpointer;
+use crate::config::{partial_path, Config, OutputFormat};
+use crate::coverage::field_coverage;
+use crate::discovery::{
+    canonical_link, filter_sitemap_links, get_unique_links, next_page_link, normalize_link,
+    sitemap_locations, DiscoveryRules, LinkDiagnostics, SitemapLocations,
+};
+use crate::extract::{
+    declared_product_count, is_empty_template, parse_product_details, parse_product_page,
+    parse_products, parse_products_in, product_page_sold_out,
+};
+use crate::fetch::{Fetcher, HttpStatus};
+use crate::filters;
+use crate::i18n::Localizer;
+use crate::identity::Matcher;
+use crate::interrupt::Interrupt;
+use crate::manifest::{Artifact, Manifest};
+use crate::output;
+use crate::politeness::{CrawlLog, RequestLog};
+use crate::ratelimit::HostRateLimiter;
+use crate::robots::{Disallowed, Robots};
+use crate::selector::SelectorHits;
+use crate::signing;
+#[cfg(feature = "sqlite")]
+use crate::store::SqliteStore;
+use crate::timings::{LinkTiming, RunTimings};
+use crate::yields::{RunYield, YieldHistory};
+use crate::BnBItem;
+
+// Guards against sitemap indexes that reference each other endlessly.
+const MAX_SITEMAPS: usize = 50;
+
+/// What a single category produced, across all of its pages.
+pub struct LinkResult {
+    pub products: Vec<BnBItem>,
+    pub timing: LinkTiming,
+    /// The page rendered the site's "no products" template rather than a
+    /// listing, so an empty yield is expected and not a scrape failure.
+    pub empty_template: bool,
+    /// Set when the first page listed nothing despite declaring products and
+    /// was fetched again.
+    pub empty_retry: Option<EmptyRetry>,
+    pub selector_hits: SelectorHits,
+}
+
+/// A category page that parsed fine but listed none of the products its
+/// toolbar declared, which is usually a transient rendering problem.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmptyRetry {
+    pub url: String,
+    pub declared: usize,
+    /// Fetches after the first one.
+    pub attempts: u32,
+    /// Whether a later fetch listed products. There is no headless
+    /// renderer to fall back to, so every retry is a delayed refetch.
+    pub recovered: bool,
+}
+
+/// Everything collected by one crawl of the site.
+#[derive(Debug, Default)]
+pub struct ScrapeRun {
+    pub items: Vec<BnBItem>,
+    pub discovered: Vec<String>,
+    pub category_yields: BTreeMap<String, usize>,
+    pub diagnostics: LinkDiagnostics,
+    pub empty_pages: usize,
+    pub timings: RunTimings,
+    pub crawl: CrawlLog,
+    pub empty_retries: Vec<EmptyRetry>,
+    pub selector_hits: SelectorHits,
+    /// The run was interrupted, so the items are only what it got to.
+    pub partial: bool,
+    /// Product photos saved by `--download-images`, listed in the manifest.
+    pub images: Vec<PathBuf>,
+    /// Category links no earlier run in the yield history discovered, set
+    /// when a full run is saved.
+    pub new_categories: Vec<String>,
+}
+
+impl ScrapeRun {
+    /// Items keyed by their discount label, the shape of the JSON output.
+    /// Labels and the items in each group are sorted, so the output and its
+    /// patch don't depend on the order the pages finished in.
+    pub fn grouped(&self) -> BTreeMap<&str, Vec<&BnBItem>> {
+        let mut grouped: BTreeMap<&str, Vec<&BnBItem>> = BTreeMap::new();
+        for item in self.items.iter() {
+            grouped
+                .entry(item.discount.as_str())
+                .or_default()
+                .push(item);
+        }
+        for items in grouped.values_mut() {
+            items.sort_by(|a, b| {
+                (&a.site, &a.link, &a.name)
+                    .cmp(&(&b.site, &b.link, &b.name))
+                    .then(a.price.total_cmp(&b.price))
+                    .then(a.price_promo.total_cmp(&b.price_promo))
+            });
+        }
+        grouped
+    }
+
+    /// Adds another site's run to this one; each keeps its own items.
+    pub fn absorb(&mut self, other: ScrapeRun) {
+        self.items.extend(other.items);
+        self.discovered.extend(other.discovered);
+        // Sites sharing a category link add up instead of overwriting.
+        for (link, listed) in other.category_yields {
+            *self.category_yields.entry(link).or_default() += listed;
+        }
+        self.diagnostics.absorb(&other.diagnostics);
+        self.empty_pages += other.empty_pages;
+        self.timings.absorb(other.timings);
+        self.crawl.absorb(other.crawl);
+        self.empty_retries.extend(other.empty_retries);
+        self.selector_hits.absorb(other.selector_hits);
+        self.partial |= other.partial;
+        self.images.extend(other.images);
+        self.new_categories.extend(other.new_categories);
+    }
+
+    /// Localized one-line-per-fact summary of the run.
+    pub fn summary(&self, localizer: &Localizer) -> Vec<String> {
+        let mut lines = vec![
+            localizer.text(
+                "summary-total-items",
+                &[("count", FluentValue::from(self.items.len()))],
+            ),
+            localizer.text(
+                "summary-empty-pages",
+                &[("count", FluentValue::from(self.empty_pages))],
+            ),
+        ];
+        if !self.empty_retries.is_empty() {
+            let recovered = self
+                .empty_retries
+                .iter()
+                .filter(|retry| retry.recovered)
+                .count();
+            lines.push(localizer.text(
+                "summary-empty-retries",
+                &[
+                    ("count", FluentValue::from(self.empty_retries.len())),
+                    ("recovered", FluentValue::from(recovered)),
+                ],
+            ));
+        }
+        for link in &self.new_categories {
+            lines.push(localizer.text(
+                "summary-new-category",
+                &[("link", FluentValue::from(link.as_str()))],
+            ));
+        }
+        for (field, entries) in &self.selector_hits.fallbacks {
+            for (entry, count) in entries {
+                lines.push(localizer.text(
+                    "summary-selector-fallback",
+                    &[
+                        ("field", FluentValue::from(field.as_str())),
+                        ("selector", FluentValue::from(entry.as_str())),
+                        ("count", FluentValue::from(*count)),
+                    ],
+                ));
+            }
+        }
+        lines
+    }
+
+    /// Where this run's output in `format` goes: beside the full output
+    /// when the run is partial.
+    fn output_path(&self, config: &Config, format: OutputFormat) -> PathBuf {
+        let path = config.output_path(format);
+        if self.partial {
+            partial_path(&path)
+        } else {
+            path
+        }
+    }
+
+    /// Writes the items in each configured format, and nothing else. The
+    /// sinks share the items in memory and write at the same time, each on
+    /// its own thread. Returns the files written, in `--format` order.
+    pub fn write_output(&self, config: &Config) -> Result<Vec<PathBuf>, Report> {
+        let grouped = self.grouped();
+        let grouped = &grouped;
+        std::thread::scope(|scope| {
+            let sinks: Vec<_> = config
+                .format
+                .iter()
+                .map(|&format| {
+                    let path = self.output_path(config, format);
+                    scope.spawn(move || {
+                        match format {
+                            OutputFormat::Json => output::write_json_atomically(&path, grouped),
+                            OutputFormat::Csv => output::write_csv_atomically(&path, &self.items),
+                        }
+                        .map(|()| path)
+                    })
+                })
+                .collect();
+            sinks
+                .into_iter()
+                .map(|sink| {
+                    sink.join()
+                        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+                })
+                .collect()
+        })
+    }
+
+    /// Writes the output in the configured format, appends this run to the
+    /// yield history and lists every file written in the manifest. A partial
+    /// run only writes its output and a manifest marking it partial, both
+    /// under [`partial_path`] names, leaving the full output, history, patch
+    /// and store as they were. The combined output of several sites leaves
+    /// the history and store to each site's own save.
+    pub fn save(&mut self, config: &Config) -> Result<(), Report> {
+        let started = Instant::now();
+        if config.partitioned() && config.site.is_some() {
+            // The site's own directories, which the first run creates.
+            let files = [
+                Some(&config.output),
+                config.yield_history.as_ref(),
+                config.manifest.as_ref(),
+                config.patch.as_ref(),
+                config.politeness_report.as_ref(),
+            ];
+            for path in files.iter().flatten() {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+            }
+        }
+        let patch = config.patch.as_ref().filter(|_| !self.partial);
+        let previous = match (patch, config.json_output()) {
+            (Some(_), Some(json)) if json.exists() => {
+                Some(serde_json::from_reader(File::open(&json)?)?)
+            }
+            (Some(_), Some(_)) => Some(Value::Object(Default::default())),
+            (Some(_), None) => {
+                warn!("--patch only applies to JSON output, skipping it");
+                None
+            }
+            (None, _) => None,
+        };
+        let outputs = self.write_output(config)?;
+        let mut artifacts = outputs
+            .iter()
+            .map(|path| Artifact::describe(path, "output"))
+            .collect::<Result<Vec<_>, _>>()?;
+        if self.partial {
+            warn!(
+                "Wrote {} items of an interrupted run to {:?}",
+                self.items.len(),
+                outputs
+            );
+        } else {
+            // The combined output of several sites is only a view: each
+            // site recorded its own history and store rows when it was saved.
+            if !(config.partitioned() && config.site.is_none()) {
+                self.record_history(config, &mut artifacts)?;
+            }
+            // A full run supersedes whatever an interrupted one left behind.
+            let stale = config
+                .format
+                .iter()
+                .map(|&format| config.output_path(format))
+                .chain([config.manifest_path()])
+                .map(|path| partial_path(&path));
+            for path in stale.filter(|path| path.exists()) {
+                fs::remove_file(path)?;
+            }
+        }
+
+        if let (Some(path), Some(previous)) = (patch, previous) {
+            let operations =
+                output::write_json_patch(path, &previous, &serde_json::to_value(self.grouped())?)?;
+            info!("Wrote {} patch operations to {:?}", operations, path);
+            artifacts.push(Artifact::describe(path, "patch")?);
+        }
+        for image in &self.images {
+            artifacts.push(Artifact::describe(image, "image")?);
+        }
+        if let Some(path) = &config.politeness_report {
+            self.crawl.write_report(path, config)?;
+            artifacts.push(Artifact::describe(path, "politeness-report")?);
+        }
+        let manifest_path = if self.partial {
+            partial_path(&config.manifest_path())
+        } else {
+            config.manifest_path()
+        };
+        let mut manifest = Manifest::new(artifacts.clone());
+        manifest.partial = self.partial;
+        manifest.save(&manifest_path)?;
+
+        if config.checksums {
+            for artifact in &artifacts {
+                signing::write_checksum(&artifact.path)?;
+            }
+            signing::write_checksum(&manifest_path)?;
+        }
+        #[cfg(feature = "signing")]
+        if let Some(key) = &config.signing_key {
+            let public_key = signing::sign_file(&manifest_path, &signing::load_signing_key(key)?)?;
+            info!("Signed {:?}, public key {}", manifest_path, public_key);
+        }
+
+        self.timings.add_sink(started.elapsed());
+        Ok(())
+    }
+
+    /// Appends this run to the yield history and the store, and notes the
+    /// categories the history hadn't seen.
+    fn record_history(
+        &mut self,
+        config: &Config,
+        artifacts: &mut Vec<Artifact>,
+    ) -> Result<(), Report> {
+        let history_file = config.yield_history_path();
+        let mut history = YieldHistory::load(&history_file)?;
+        self.new_categories = history.record(
+            RunYield::new(
+                self.category_yields.clone(),
+                self.discovered.iter().cloned().collect(),
+            )
+            .with_coverage(field_coverage(&self.items)),
+            config.yield_drop_alert,
+        );
+        history.save(&history_file)?;
+        artifacts.push(Artifact::describe(&history_file, "yield-history")?);
+
+        #[cfg(feature = "sqlite")]
+        if let Some(store) = &config.store {
+            let mut db = SqliteStore::open(store)?.with_review_factor(config.review_factor);
+            let run_id = db.record_run(&self.items, &config.root_url)?;
+            let queued = db.held_back(run_id)?;
+            if queued > 0 {
+                warn!(
+                    "Held back {} outlier prices from the store, see `bnbscraper review list`",
+                    queued
+                );
+            }
+            if let Some(path) = store.strip_prefix("sqlite://") {
+                artifacts.push(Artifact::describe(std::path::Path::new(path), "store")?);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Crawls the site described by a [`Config`] through the given fetcher.
+/// Nothing here spawns tasks or touches global state, so it runs on
+/// whatever executor drives it.
+pub struct Scraper {
+    fetcher: Box<dyn Fetcher>,
+    limiter: HostRateLimiter,
+    budget: RunBudget,
+    requests: RequestLog,
+    interrupt: Interrupt,
+    /// Loaded before the first page; None with `--ignore-robots`.
+    robots: OnceCell<Option<Robots>>,
+    config: Config,
+}
+
+impl Scraper {
+    pub fn new(fetcher: impl Fetcher + 'static, config: Config) -> Self {
+        Scraper {
+            fetcher: Box::new(fetcher),
+            limiter: HostRateLimiter::from_config(&config),
+            budget: RunBudget::new(config.max_runtime()),
+            requests: RequestLog::default(),
+            interrupt: Interrupt::default(),
+            robots: OnceCell::new(),
+            config,
+        }
+    }
+
+    /// Shares another scraper's `--max-runtime` budget, so scraping several
+    /// sites in one run stays within a single budget.
+    pub fn with_budget(self, budget: RunBudget) -> Self {
+        Scraper { budget, ..self }
+    }
+
+    /// Stops crawling when `interrupt` is set, returning a partial run with
+    /// the items scraped until then.
+    pub fn with_interrupt(self, interrupt: Interrupt) -> Self {
+        Scraper { interrupt, ..self }
+    }
+
+    /// `next`, unless the run is interrupted first: then None, like the end of
+    /// a stream, and whatever `next` was waiting on is dropped.
+    async fn unless_interrupted<T>(&self, next: impl Future<Output = Option<T>>) -> Option<T> {
+        // Checked first, so a page that's ready doesn't slip in after it.
+        tokio::select! {
+            biased;
+            _ = self.interrupt.wait() => None,
+            next = next => next,
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Discovers category links on the landing page and scrapes every one,
+    /// plus the configured seed pages.
+    pub async fn scrape_all(&self) -> Result<ScrapeRun, Report> {
+        info!("Starting Bath And Body Works scraper...");
+        let config = &self.config;
+        let mut run = ScrapeRun::default();
+        let robots = self.robots().await;
+
+        let rules = DiscoveryRules::from_config(config);
+        let mut discovered = BTreeSet::new();
+        let mut sitemap_products = vec![];
+        let mut use_homepage = config.discovery.uses_homepage();
+        if config.discovery.uses_sitemap() {
+            match self.sitemap_locations().await {
+                Ok(locations) => {
+                    discovered.extend(filter_sitemap_links(
+                        locations.pages,
+                        &rules,
+                        &mut run.diagnostics,
+                    ));
+                    sitemap_products =
+                        filter_sitemap_links(locations.products, &rules, &mut run.diagnostics);
+                }
+                Err(err) => {
+                    warn!(
+                        "Could not read the sitemap, discovering from the landing page instead: {}",
+                        err
+                    );
+                    use_homepage = true;
+                }
+            }
+        }
+        if use_homepage {
+            let started = Instant::now();
+            let res = self.fetch(config.root_url.as_str()).await?;
+            let fetched = Instant::now();
+
+            let document = Document::from(res.as_str());
+            let links = document.find(Name("a"));
+            discovered.extend(get_unique_links(links, &rules, &mut run.diagnostics));
+            run.timings.record_link(LinkTiming {
+                url: config.root_url.to_string(),
+                fetch: fetched - started,
+                parse: fetched.elapsed(),
+            });
+        }
+        run.discovered = discovered.into_iter().collect();
+
+        for seed in config.seed_urls() {
+            if !run.discovered.contains(&seed) {
+                run.discovered.push(seed);
+            }
+        }
+        // Dropped here rather than failing in fetch, to count them.
+        if let Some(robots) = robots {
+            let before = run.discovered.len() + sitemap_products.len();
+            run.discovered.retain(|link| robots.allows(link));
+            sitemap_products.retain(|link| robots.allows(link));
+            run.diagnostics.robots_disallowed =
+                before - run.discovered.len() - sitemap_products.len();
+        }
+        info!("Category links discovered...");
+        info!("Skipped anchors: {:?}", run.diagnostics);
+
+        let mut checkpointer = Checkpointer::new(config);
+        self.scrape_discovered(&mut run, &mut checkpointer).await?;
+        self.scrape_sitemap_products(&mut run, &sitemap_products)
+            .await;
+        if config.deep && !self.interrupt.is_set() {
+            self.enrich_items(&mut run.items, &mut checkpointer).await;
+        }
+        checkpointer.flush();
+        if let Some(robots) = robots {
+            self.requests.check_robots(robots);
+        }
+        run.crawl = self.crawl_log(run.diagnostics.robots_disallowed);
+        run.partial = self.interrupt.is_set();
+        info!("Finished!");
+
+        Ok(run)
+    }
+
+    /// The site's robots.txt, fetched once and applying its Crawl-delay;
+    /// if it can't be fetched, everything is allowed.
+    async fn robots(&self) -> Option<&Robots> {
+        self.robots
+            .get_or_init(|| async {
+                if self.config.ignore_robots {
+                    return None;
+                }
+                let url = match self.config.root_url.join("/robots.txt") {
+                    Ok(url) => url,
+                    Err(_) => return Some(Robots::default()),
+                };
+                let robots = match self.fetch_page(url.as_str()).await {
+                    Ok(text) => Robots::parse(&text),
+                    Err(err) => {
+                        warn!("Could not fetch {}: {}", url, err);
+                        Robots::default()
+                    }
+                };
+                if let Some(delay) = robots.crawl_delay {
+                    self.limiter.raise_interval(delay);
+                }
+                Some(robots)
+            })
+            .await
+            .as_ref()
+    }
+
+    /// Every page and product URL listed in /sitemap.xml and the sitemaps it
+    /// indexes, reading at most `MAX_SITEMAPS` sitemap files.
+    async fn sitemap_locations(&self) -> Result<SitemapLocations, Report> {
+        let mut queue = vec![self.config.root_url.join("/sitemap.xml")?.to_string()];
+        let mut read = vec![];
+        let mut all = SitemapLocations::default();
+
+        while let Some(sitemap) = queue.pop() {
+            if read.contains(&sitemap) || read.len() >= MAX_SITEMAPS {
+                continue;
+            }
+            let xml = self.fetch(&sitemap).await?;
+            let locations = sitemap_locations(&xml);
+            queue.extend(locations.sitemaps);
+            all.pages.extend(locations.pages);
+            all.products.extend(locations.products);
+            read.push(sitemap);
+        }
+
+        info!(
+            "Read {} sitemaps listing {} pages and {} products",
+            read.len(),
+            all.pages.len(),
+            all.products.len()
+        );
+        Ok(all)
+    }
+
+    /// Reads the sitemap's product pages that no listing already covered,
+    /// keeping the items the filters let through.
+    async fn scrape_sitemap_products(&self, run: &mut ScrapeRun, links: &[String]) {
+        let root = &self.config.root_url;
+        let listed: BTreeSet<String> = run
+            .items
+            .iter()
+            .map(|item| canonical_link(root, &item.link))
+            .collect();
+        let pending: Vec<String> = links
+            .iter()
+            .filter(|link| !listed.contains(&canonical_link(root, link)))
+            .cloned()
+            .collect();
+        if pending.is_empty() || self.interrupt.is_set() {
+            return;
+        }
+        let mut products = ScrapeRun::default();
+        self.scrape_product_pages(&mut products, &pending).await;
+        run.timings.absorb(products.timings);
+        run.items.extend(
+            products
+                .items
+                .into_iter()
+                .filter(|item| filters::keep(item, &self.config)),
+        );
+    }
+
+    /// Scrapes the site's own search results for `query`, following their
+    /// pagination like a category.
+    pub async fn scrape_search(&self, query: &str) -> Result<ScrapeRun, Report> {
+        let mut url = self.config.root_url.join(&self.config.search_path)?;
+        url.query_pairs_mut()
+            .append_pair(&self.config.search_param, query);
+        info!("Searching the site for {:?}", query);
+
+        let mut run = ScrapeRun {
+            discovered: vec![url.into()],
+            ..ScrapeRun::default()
+        };
+        self.scrape_discovered(&mut run, &mut Checkpointer::disabled())
+            .await?;
+        run.crawl = self.crawl_log(0);
+        run.partial = self.interrupt.is_set();
+        Ok(run)
+    }
+
+    /// Scrapes only the given product pages, with the product-page extractor,
+    /// keeping their order. Pages that fail are left out.
+    pub async fn scrape_products(&self, links: &[String]) -> ScrapeRun {
+        let mut run = ScrapeRun {
+            discovered: links.to_vec(),
+            ..ScrapeRun::default()
+        };
+        self.scrape_product_pages(&mut run, links).await;
+        run.crawl = self.crawl_log(0);
+        run.partial = self.interrupt.is_set();
+        run
+    }
+
+    /// Fetches and extracts each product page into `run.items`, in order.
+    async fn scrape_product_pages(&self, run: &mut ScrapeRun, links: &[String]) {
+        let mut pages = stream::iter(links)
+            .map(|link| async move {
+                let started = Instant::now();
+                (link, started, self.fetch(link).await)
+            })
+            .buffered(self.config.deep_concurrency.max(1));
+
+        while let Some((link, started, page)) = self.unless_interrupted(pages.next()).await {
+            let fetched = Instant::now();
+            match page {
+                Ok(html) => {
+                    let mut item = parse_product_page(&html, link, &self.config);
+                    if let Some(site) = self.config.site {
+                        site.tag(&mut item);
+                    }
+                    run.items.push(item);
+                    run.timings.record_link(LinkTiming {
+                        url: link.clone(),
+                        fetch: fetched - started,
+                        parse: fetched.elapsed(),
+                    });
+                }
+                Err(err) => warn!("Failed to fetch {}: {}", link, err),
+            }
+        }
+    }
+
+    /// Hands over the requests sent so far for the politeness report.
+    pub fn crawl_log(&self, disallowed_links_skipped: usize) -> CrawlLog {
+        CrawlLog {
+            requests: self.requests.take(),
+            disallowed_links_skipped,
+            enforced_interval: self.limiter.interval(),
+        }
+    }
+
+    /// Scrapes every link in `run.discovered`, filtering and de-duplicating
+    /// the items into `run.items`. Categories the checkpoint already covers
+    /// aren't fetched again; their saved items are used instead.
+    async fn scrape_discovered(
+        &self,
+        run: &mut ScrapeRun,
+        checkpointer: &mut Checkpointer,
+    ) -> Result<(), Report> {
+        let config = &self.config;
+        let strategies: Vec<&str> = config.match_strategies.iter().map(String::as_str).collect();
+        let mut buffer = ItemBuffer::new(
+            config.buffer_limit,
+            Matcher::from_names(&strategies, config.match_threshold)?,
+        );
+        for (link, listed) in &checkpointer.progress.categories {
+            match listed {
+                Some(listed) => {
+                    run.category_yields.insert(link.clone(), *listed);
+                }
+                None => run.empty_pages += 1,
+            }
+        }
+        for item in std::mem::take(&mut checkpointer.progress.items) {
+            buffer.push(item)?;
+        }
+        let pending: Vec<String> = run
+            .discovered
+            .iter()
+            .filter(|link| !checkpointer.is_done(link))
+            .cloned()
+            .collect();
+        let mut items_futures = stream::iter(pending)
+            .map(|link| async move {
+                let result = AssertUnwindSafe(self.scrape_category(&link))
+                    .catch_unwind()
+                    .await
+                    .unwrap_or_else(|panic| Err(eyre!("panicked: {}", panic_message(&panic))));
+                (link, result)
+            })
+            .buffer_unordered(config.max_concurrency.max(1));
+
+        while let Some((link, result)) = self.unless_interrupted(items_futures.next()).await {
+            match result {
+                Ok(result) if result.empty_template => {
+                    info!("No products listed on {}", link);
+                    run.timings.record_link(result.timing);
+                    run.empty_pages += 1;
+                    checkpointer.record_category(&link, None, &[]);
+                }
+                Ok(result) => {
+                    run.timings.record_link(result.timing);
+                    run.empty_retries.extend(result.empty_retry);
+                    run.selector_hits.absorb(result.selector_hits);
+                    run.category_yields
+                        .insert(link.clone(), result.products.len());
+                    let sink_started = Instant::now();
+                    let listed = result.products.len();
+                    let mut kept = vec![];
+                    for mut product in result.products {
+                        if let Some(site) = config.site {
+                            site.tag(&mut product);
+                        }
+                        if filters::keep(&product, config) {
+                            kept.push(product);
+                        }
+                    }
+                    checkpointer.record_category(&link, Some(listed), &kept);
+                    for product in kept {
+                        buffer.push(product)?;
+                    }
+                    run.timings.add_sink(sink_started.elapsed());
+                }
+                Err(err) => warn!("Failed to process {}: {}", link, err),
+            }
+        }
+
+        let sink_started = Instant::now();
+        run.items = buffer.into_items()?;
+        run.timings.add_sink(sink_started.elapsed());
+
+        Ok(())
+    }
+
+    /// Second fetch stage of `--deep` mode: fills in each item's details from
+    /// its product page, best deals first, until the `--max-runtime` budget
+    /// runs out. Items whose page fails or isn't reached keep `details: None`.
+    /// Pages the checkpoint already covers aren't fetched again.
+    pub async fn enrich_items(&self, items: &mut [BnBItem], checkpointer: &mut Checkpointer) {
+        let budget = self.budget;
+        let root = &self.config.root_url;
+        for item in items.iter_mut() {
+            if let Some(page) = checkpointer.progress.details.get(&item.link) {
+                item.details = Some(page.details.clone());
+                item.available &= page.available;
+            }
+        }
+        let mut queue: Vec<&mut BnBItem> = items
+            .iter_mut()
+            .filter(|item| item.details.is_none())
+            .collect();
+        queue.sort_by(|a, b| by_detail_priority(a, b));
+        let total = queue.len();
+
+        let mut pages = stream::iter(queue)
+            .take_while(|_| future::ready(!budget.exhausted()))
+            .map(|item| async move {
+                let page = match normalize_link(root, &item.link) {
+                    Some(url) if !item.link.is_empty() => self.fetch(&url).await,
+                    _ => Err(eyre!("no product link")),
+                };
+                (item, page)
+            })
+            .buffer_unordered(self.config.deep_concurrency.max(1));
+
+        let mut enriched = 0;
+        let mut attempted = 0;
+        loop {
+            let next = self
+                .unless_interrupted(async {
+                    match budget.deadline() {
+                        // Running out of time ends the stage like running out of items.
+                        Some(deadline) => timeout_at(deadline.into(), pages.next())
+                            .await
+                            .unwrap_or_default(),
+                        None => pages.next().await,
+                    }
+                })
+                .await;
+            let (item, page) = match next {
+                Some(next) => next,
+                None => break,
+            };
+            attempted += 1;
+            match page {
+                Ok(html) => {
+                    let details = parse_product_details(&html);
+                    let in_stock = !product_page_sold_out(&html);
+                    checkpointer.record_details(&item.link, &details, in_stock);
+                    item.details = Some(details);
+                    if let Some(provenance) = &mut item.provenance {
+                        provenance.insert("details".to_string(), "product-page".to_string());
+                    }
+                    item.available &= in_stock;
+                    enriched += 1;
+                }
+                Err(err) => warn!("Failed to fetch details of {:?}: {}", item.name, err),
+            }
+        }
+        info!("Fetched details for {} products", enriched);
+        if attempted < total && !self.interrupt.is_set() {
+            warn!(
+                "Ran out of time before the details of {} products",
+                total - attempted
+            );
+        }
+    }
+
+    /// Fetches and parses a category, following its pagination up to
+    /// `--max-pages` pages.
+    pub async fn scrape_category(&self, link: &str) -> Result<LinkResult, Report> {
+        info!("Processing link: {}", link);
+        let is_seed = self.config.seed_urls().iter().any(|seed| seed == link);
+        let mut products: Vec<BnBItem> = vec![];
+        let mut empty_template = false;
+        let mut timing = LinkTiming {
+            url: link.to_string(),
+            ..LinkTiming::default()
+        };
+
+        // Hits are counted per page so a refetch replaces rather than adds.
+        let parse = |html: &str| {
+            let mut hits = SelectorHits::default();
+            let products = if is_seed {
+                parse_products_in(html, &self.config.seed_item_class, &self.config, &mut hits)
+            } else {
+                parse_products(html, &self.config, &mut hits)
+            };
+            (products, hits)
+        };
+
+        let mut empty_retry = None;
+        let mut selector_hits = SelectorHits::default();
+        let mut visited = vec![];
+        let mut page = Some(link.to_string());
+        while let Some(url) = page.take() {
+            // The empty-page delay counts as neither fetching nor parsing.
+            let started = Instant::now();
+            let mut res = self.fetch(&url).await?;
+            timing.fetch += started.elapsed();
+            let mut parsing = Instant::now();
+            let (mut page_products, mut page_hits) = parse(&res);
+            if visited.is_empty() && page_products.is_empty() {
+                empty_template = is_empty_template(&res, &self.config);
+                if let Some(declared) = declared_product_count(&res).filter(|count| *count > 0) {
+                    let mut retry = EmptyRetry {
+                        url: url.clone(),
+                        declared,
+                        attempts: 0,
+                        recovered: false,
+                    };
+                    while !retry.recovered && retry.attempts < self.config.empty_retries {
+                        warn!(
+                            "{} declares {} products but lists none, fetching it again",
+                            url, declared
+                        );
+                        timing.parse += parsing.elapsed();
+                        tokio::time::sleep(Duration::from_millis(self.config.empty_retry_delay_ms))
+                            .await;
+                        let started = Instant::now();
+                        res = self.fetch(&url).await?;
+                        timing.fetch += started.elapsed();
+                        parsing = Instant::now();
+                        (page_products, page_hits) = parse(&res);
+                        retry.attempts += 1;
+                        retry.recovered = !page_products.is_empty();
+                    }
+                    if retry.attempts > 0 {
+                        empty_template &= !retry.recovered;
+                        empty_retry = Some(retry);
+                    }
+                }
+            }
+            selector_hits.absorb(page_hits);
+
+            let found_new = page_products.iter().any(|item| !products.contains(item));
+            for item in page_products {
+                if !products.contains(&item) {
+                    products.push(item);
+                }
+            }
+
+            visited.push(url.clone());
+            // A page with nothing new means the site is repeating its last page.
+            if found_new && visited.len() < self.config.max_pages {
+                page = Url::parse(&url)
+                    .ok()
+                    .and_then(|current| next_page_link(&Document::from(res.as_str()), &current))
+                    .filter(|next| !visited.contains(next));
+            }
+
+            timing.parse += parsing.elapsed();
+        }
+
+        if visited.len() > 1 {
+            info!("Followed {} pages of {}", visited.len(), link);
+        }
+        Ok(LinkResult {
+            products,
+            timing,
+            empty_template,
+            empty_retry,
+            selector_hits,
+        })
+    }
+
+    /// Fetches a page robots.txt allows, unless `--ignore-robots` is set.
+    /// Every request of a crawl goes through here.
+    async fn fetch(&self, url: &str) -> Result<String, Report> {
+        match self.robots().await {
+            Some(robots) if !robots.allows(url) => Err(Disallowed(url.to_string()).into()),
+            _ => self.fetch_page(url).await,
+        }
+    }
+
+    /// Sends a request the page fetcher can't make, like a HEAD or an image
+    /// download, under the same robots.txt check, rate limit and request log
+    /// as the pages. `send` yields its value and the bytes that came back.
+    /// robots.txt only speaks for the site's own host, so URLs elsewhere (an
+    /// image CDN) skip that check.
+    pub async fn request<T, Fut>(&self, url: &str, send: impl FnOnce() -> Fut) -> Result<T, Report>
+    where
+        Fut: Future<Output = Result<(T, usize), Report>>,
+    {
+        let on_site = Url::parse(url)
+            .is_ok_and(|parsed| parsed.host_str() == self.config.root_url.host_str());
+        if on_site {
+            if let Some(robots) = self.robots().await {
+                if !robots.allows(url) {
+                    return Err(Disallowed(url.to_string()).into());
+                }
+            }
+        }
+        self.limiter.wait(url).await;
+        let result = send().await;
+        let bytes = result.as_ref().map_or(0, |(_, bytes)| *bytes);
+        self.requests.record(url, bytes, result.is_ok(), false);
+        result.map(|(value, _)| value)
+    }
+
+    /// Fetches through the configured fetcher and rate limiter, retrying failures with
+    /// exponential backoff plus up to 50% random jitter so parallel retries
+    /// don't hit the site in lockstep. Statuses that won't change on a retry,
+    /// like a 404, fail at once.
+    async fn fetch_page(&self, url: &str) -> Result<String, Report> {
+        let mut attempt = 0;
+        loop {
+            self.limiter.wait(url).await;
+            let result = self.fetcher.fetch(url).await;
+            let bytes = result.as_ref().map(String::len).unwrap_or_default();
+            self.requests
+                .record(url, bytes, result.is_ok(), attempt > 0);
+            match result {
+                Ok(body) => return Ok(body),
+                Err(err) if attempt < self.config.retries && is_retryable(&err) => {
+                    let backoff = self.config.backoff_ms.saturating_mul(1 << attempt.min(16));
+                    let jitter = rand::thread_rng().gen_range(0..=backoff / 2);
+                    warn!(
+                        "Fetching {} failed ({}), retrying in {}ms",
+                        url,
+                        err,
+                        backoff + jitter
+                    );
+                    tokio::time::sleep(Duration::from_millis(backoff + jitter)).await;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+fn is_retryable(err: &Report) -> bool {
+    err.downcast_ref::<HttpStatus>()
+        .is_none_or(HttpStatus::is_retryable)
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use reqwest::StatusCode;
+
+    use super::*;
+    use crate::discovery::DiscoveryMode;
+    use crate::site::Site;
+
+    #[derive(Clone)]
+    struct StaticPages(HashMap<String, String>);
+
+    #[async_trait]
+    impl Fetcher for StaticPages {
+        async fn fetch(&self, url: &str) -> Result<String, Report> {
+            self.0
+                .get(url)
+                .cloned()
+                .ok_or_else(|| eyre!("no page for {}", url))
+        }
+    }
+
+    #[tokio::test]
+    async fn scrapes_every_discovered_category() {
+        let config = Config {
+            retries: 0,
+            ..Config::default()
+        };
+        let root = config.root_url.to_string();
+        let pages = StaticPages(
+            vec![
+                (
+                    root.clone(),
+                    r#"<nav><a href="/velas">Velas</a><a href="/rotas">Rotas</a></nav>"#
+                        .to_string(),
+                ),
+                (
+                    format!("{}velas", root),
+                    include_str!("../fixtures/category_listing.html").to_string(),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        );
+
+        let run = Scraper::new(pages, config).scrape_all().await.unwrap();
+        assert_eq!(run.discovered.len(), 2);
+        assert_eq!(run.items.len(), 3);
+        assert_eq!(run.category_yields.len(), 1);
+        assert_eq!(run.grouped()["2x1"].len(), 1);
+    }
+
+    #[cfg(feature = "patch")]
+    #[test]
+    fn the_same_items_in_another_order_patch_to_nothing() {
+        let item = |name: &str, discount: &str| BnBItem {
+            name: name.to_string(),
+            link: format!("/velas/{}", name),
+            discount: discount.to_string(),
+            ..BnBItem::default()
+        };
+        let mut items = vec![item("uno", "2x1"), item("dos", ""), item("tres", "2x1")];
+        let previous = ScrapeRun {
+            items: items.clone(),
+            ..ScrapeRun::default()
+        };
+        items.reverse();
+        let current = ScrapeRun {
+            items,
+            ..ScrapeRun::default()
+        };
+
+        let path =
+            std::env::temp_dir().join(format!("bnbscraper-order-{}.patch", std::process::id()));
+        let operations = output::write_json_patch(
+            &path,
+            &serde_json::to_value(previous.grouped()).unwrap(),
+            &serde_json::to_value(current.grouped()).unwrap(),
+        )
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(operations, 0);
+    }
+
+    #[tokio::test]
+    async fn resumes_without_refetching_finished_categories() {
+        let checkpoint =
+            std::env::temp_dir().join(format!("bnbscraper-resume-{}.json", std::process::id()));
+        let config = Config {
+            retries: 0,
+            checkpoint: Some(checkpoint.clone()),
+            ..Config::default()
+        };
+        let root = config.root_url.to_string();
+        let homepage = (
+            root.clone(),
+            r#"<nav><a href="/velas">Velas</a><a href="/jabones">Jabones</a></nav>"#.to_string(),
+        );
+        // The first run dies before /jabones loads, the second could no
+        // longer fetch /velas.
+        let first = StaticPages(
+            vec![
+                homepage.clone(),
+                (
+                    format!("{}velas", root),
+                    include_str!("../fixtures/category_listing.html").to_string(),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        let second = StaticPages(
+            vec![
+                homepage,
+                (
+                    format!("{}jabones", root),
+                    r#"<li class="product-item"><div class="product-item__caption"><a href="/jabones/uno">Jabón</a></div></li>"#
+                        .to_string(),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        );
+
+        Scraper::new(first, config.clone())
+            .scrape_all()
+            .await
+            .unwrap();
+        let resumed = Config {
+            resume: true,
+            ..config.clone()
+        };
+        let run = Scraper::new(second, resumed).scrape_all().await.unwrap();
+        crate::checkpoint::clear(&config).unwrap();
+
+        assert_eq!(run.items.len(), 4);
+        assert_eq!(run.category_yields.len(), 2);
+    }
+
+    #[test]
+    fn writes_every_format_from_one_run() {
+        let dir = std::env::temp_dir().join(format!("bnbscraper-formats-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = Config {
+            output: dir.join("items"),
+            format: vec![OutputFormat::Csv, OutputFormat::Json],
+            ..Config::default()
+        };
+        let run = ScrapeRun {
+            items: vec![BnBItem {
+                name: "Uno".to_string(),
+                discount: "10%".to_string(),
+                ..BnBItem::default()
+            }],
+            ..ScrapeRun::default()
+        };
+
+        let written = run.write_output(&config).unwrap();
+        let csv = std::fs::read_to_string(dir.join("items.csv")).unwrap();
+        let grouped = output::read_grouped_json(&dir.join("items.json")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(written, vec![dir.join("items.csv"), dir.join("items.json")]);
+        assert!(csv.lines().nth(1).unwrap().starts_with("Uno,"));
+        assert_eq!(grouped["10%"][0].name, "Uno");
+    }
+
+    #[test]
+    fn partial_runs_leave_the_full_output_alone() {
+        let dir = std::env::temp_dir().join(format!("bnbscraper-partial-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = Config {
+            output: dir.join("items.json"),
+            yield_history: Some(dir.join("yields.json")),
+            ..Config::default()
+        };
+        let item = |name: &str| BnBItem {
+            name: name.to_string(),
+            ..BnBItem::default()
+        };
+        let mut full = ScrapeRun {
+            items: vec![item("Uno"), item("Dos")],
+            ..ScrapeRun::default()
+        };
+        full.save(&config).unwrap();
+        let mut partial = ScrapeRun {
+            items: vec![item("Uno")],
+            partial: true,
+            ..ScrapeRun::default()
+        };
+        partial.save(&config).unwrap();
+
+        let kept = output::read_grouped_json(&dir.join("items.json")).unwrap();
+        let interrupted = output::read_grouped_json(&dir.join("items.partial.json")).unwrap();
+        let manifest: Manifest = serde_json::from_str(
+            &std::fs::read_to_string(dir.join("manifest.partial.json")).unwrap(),
+        )
+        .unwrap();
+        full.save(&config).unwrap();
+        let superseded = dir.join("items.partial.json").exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(kept[""].len(), 2);
+        assert_eq!(interrupted[""].len(), 1);
+        assert!(manifest.partial);
+        assert!(!superseded);
+    }
+
+    #[test]
+    fn keeps_the_history_of_each_site_apart() {
+        let dir = std::env::temp_dir().join(format!("bnbscraper-sites-{}", std::process::id()));
+        let config = Config {
+            output: dir.join("items.json"),
+            sites: vec![Site::Mx, Site::Us],
+            ..Config::default()
+        };
+        let mut combined = ScrapeRun::default();
+        for site_config in config.site_configs() {
+            let mut site_run = ScrapeRun {
+                items: vec![BnBItem {
+                    name: "Uno".to_string(),
+                    site: site_config.site.unwrap().code().to_string(),
+                    ..BnBItem::default()
+                }],
+                ..ScrapeRun::default()
+            };
+            site_run.save(&site_config).unwrap();
+            combined.absorb(site_run);
+        }
+        combined.save(&config).unwrap();
+
+        let us = output::read_grouped_json(&dir.join("us/items.json")).unwrap();
+        let all = output::read_grouped_json(&dir.join("items.json")).unwrap();
+        let histories = (
+            dir.join("mx/yield_history.json").exists(),
+            dir.join("us/yield_history.json").exists(),
+            dir.join("yield_history.json").exists(),
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(us[""].len(), 1);
+        assert_eq!(all[""].len(), 2);
+        assert_eq!(histories, (true, true, false));
+    }
+
+    #[tokio::test]
+    async fn stops_crawling_once_interrupted() {
+        let config = Config {
+            retries: 0,
+            ..Config::default()
+        };
+        let root = config.root_url.to_string();
+        let pages = StaticPages(
+            vec![
+                (
+                    root.clone(),
+                    r#"<nav><a href="/velas">Velas</a></nav>"#.to_string(),
+                ),
+                (
+                    format!("{}velas", root),
+                    include_str!("../fixtures/category_listing.html").to_string(),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        let interrupt = Interrupt::default();
+        interrupt.trigger();
+
+        let run = Scraper::new(pages, config)
+            .with_interrupt(interrupt)
+            .scrape_all()
+            .await
+            .unwrap();
+
+        assert!(run.partial);
+        assert!(run.items.is_empty());
+    }
+
+    #[tokio::test]
+    async fn skips_product_pages_robots_txt_disallows() {
+        let config = Config {
+            retries: 0,
+            deep: true,
+            ..Config::default()
+        };
+        let root = config.root_url.to_string();
+        let pages = StaticPages(
+            vec![
+                (
+                    format!("{}robots.txt", root),
+                    "User-agent: *\nDisallow: /velas/vela-3-mechas-producto-uno\n".to_string(),
+                ),
+                (
+                    root.clone(),
+                    r#"<nav><a href="/velas">Velas</a></nav>"#.to_string(),
+                ),
+                (
+                    format!("{}velas", root),
+                    include_str!("../fixtures/category_listing.html").to_string(),
+                ),
+                (
+                    format!("{}velas/vela-3-mechas-producto-uno", root),
+                    r#"<div class="product attribute sku"><div class="value">026</div></div>"#
+                        .to_string(),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        );
+
+        let run = Scraper::new(pages, config).scrape_all().await.unwrap();
+
+        assert_eq!(run.items.len(), 3);
+        assert!(run.items.iter().all(|item| item.details.is_none()));
+    }
+
+    #[tokio::test]
+    async fn reads_sitemap_products_from_their_pages() {
+        let config = Config {
+            retries: 0,
+            discovery: DiscoveryMode::Sitemap,
+            ..Config::default()
+        };
+        let root = config.root_url.to_string();
+        let sitemap = format!(
+            r#"<urlset>
+                 <url><loc>{root}velas</loc></url>
+                 <url><loc>{root}velas/vela-nueva</loc><image:image><image:loc>{root}uno.jpg</image:loc></image:image></url>
+               </urlset>"#,
+            root = root
+        );
+        let pages = StaticPages(
+            vec![
+                (format!("{}sitemap.xml", root), sitemap),
+                (
+                    format!("{}velas", root),
+                    include_str!("../fixtures/category_listing.html").to_string(),
+                ),
+                (
+                    format!("{}velas/vela-nueva", root),
+                    r#"<h1 class="page-title">Vela Nueva</h1>"#.to_string(),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        );
+
+        let run = Scraper::new(pages, config.clone())
+            .scrape_all()
+            .await
+            .unwrap();
+        assert_eq!(run.discovered, vec![format!("{}velas", root)]);
+        assert_eq!(run.items.len(), 4);
+        assert!(run.items.iter().any(|item| item.name == "Vela Nueva"));
+
+        // Without a sitemap the landing page is crawled instead.
+        let pages = StaticPages(
+            vec![(
+                root.clone(),
+                r#"<nav><a href="/velas">Velas</a></nav>"#.to_string(),
+            )]
+            .into_iter()
+            .collect(),
+        );
+        let run = Scraper::new(pages, config).scrape_all().await.unwrap();
+        assert_eq!(run.discovered, vec![format!("{}velas", root)]);
+    }
+
+    #[tokio::test]
+    async fn sends_other_requests_under_robots_txt() {
+        let config = Config::default();
+        let root = config.root_url.to_string();
+        let pages = StaticPages(
+            vec![(
+                format!("{}robots.txt", root),
+                "User-agent: *\nDisallow: /privado\n".to_string(),
+            )]
+            .into_iter()
+            .collect(),
+        );
+        let scraper = Scraper::new(pages, config);
+        let sent = AtomicU32::new(0);
+        let send = || async {
+            sent.fetch_add(1, Ordering::SeqCst);
+            Ok(((), 10))
+        };
+
+        let denied = scraper.request(&format!("{}privado/uno", root), send).await;
+        let allowed = scraper.request(&format!("{}velas/uno", root), send).await;
+        let elsewhere = scraper
+            .request("https://cdn.example.com/privado/uno.jpg", send)
+            .await;
+
+        assert!(denied.is_err());
+        assert!(allowed.is_ok() && elsewhere.is_ok());
+        assert_eq!(sent.load(Ordering::SeqCst), 2);
+        let logged: Vec<usize> = scraper
+            .crawl_log(0)
+            .requests
+            .iter()
+            .map(|request| request.bytes)
+            .collect();
+        // robots.txt itself, then the two that were sent.
+        assert_eq!(logged.len(), 3);
+        assert_eq!(&logged[1..], [10, 10]);
+    }
+
+    #[tokio::test]
+    async fn ignoring_robots_txt_keeps_the_configured_delay() {
+        let robots = |ignore_robots| {
+            let config = Config {
+                ignore_robots,
+                min_delay_ms: 5,
+                ..Config::default()
+            };
+            let pages = StaticPages(
+                vec![(
+                    format!("{}robots.txt", config.root_url),
+                    "User-agent: *\nCrawl-delay: 10\n".to_string(),
+                )]
+                .into_iter()
+                .collect(),
+            );
+            Scraper::new(pages, config)
+        };
+
+        let polite = robots(false);
+        polite.robots().await;
+        let ignoring = robots(true);
+        ignoring.robots().await;
+
+        assert_eq!(polite.limiter.interval(), Duration::from_secs(10));
+        assert_eq!(ignoring.limiter.interval(), Duration::from_millis(5));
+    }
+
+    #[tokio::test]
+    async fn tags_items_with_their_site() {
+        let config = Config {
+            retries: 0,
+            sites: vec![Site::Mx, Site::Us],
+            ..Config::default()
+        };
+        let pages = StaticPages(
+            vec![
+                (
+                    Site::Mx.root_url().to_string(),
+                    r#"<nav><a href="/velas">Velas</a></nav>"#.to_string(),
+                ),
+                (
+                    format!("{}velas", Site::Mx.root_url()),
+                    include_str!("../fixtures/category_listing.html").to_string(),
+                ),
+                (
+                    Site::Us.root_url().to_string(),
+                    r#"<nav><a href="/c/home-fragrance/candles">Candles</a></nav>"#.to_string(),
+                ),
+                (
+                    format!("{}c/home-fragrance/candles", Site::Us.root_url()),
+                    r#"<div class="product-tile">
+                        <div class="product-name"><a href="/p/candle.html?cgid=candles">Candle</a></div>
+                        <div class="price"><span class="standard-price">$26.95</span></div>
+                    </div>"#
+                        .to_string(),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        );
+
+        let mut run = ScrapeRun::default();
+        for site_config in config.site_configs() {
+            let scraper = Scraper::new(pages.clone(), site_config);
+            run.absorb(scraper.scrape_all().await.unwrap());
+        }
+
+        assert_eq!(run.items.len(), 4);
+        assert!(run.items[..3].iter().all(|item| item.site == "mx"));
+        assert_eq!(run.items[3].site, "us");
+        assert_eq!(
+            run.items[3].link,
+            "https://www.bathandbodyworks.com/p/candle.html"
+        );
+        assert_eq!(run.items[3].price, 26.95);
+    }
+
+    #[tokio::test]
+    async fn scrapes_seed_pages_with_their_own_tile_class() {
+        let config = Config {
+            retries: 0,
+            seeds: vec!["/buscar?q=outlet".to_string()],
+            seed_item_class: "outlet-tile".to_string(),
+            ..Config::default()
+        };
+        let root = config.root_url.to_string();
+        let pages = StaticPages(
+            vec![
+                (root.clone(), "<nav></nav>".to_string()),
+                (
+                    format!("{}buscar?q=outlet", root),
+                    r#"<div class="outlet-tile">
+                        <div class="product-item__caption"><a href="/velas/uno">Producto Uno</a></div>
+                        <div class="product-item__price"><span>$99.00</span></div>
+                    </div>"#
+                        .to_string(),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        );
+
+        let run = Scraper::new(pages, config).scrape_all().await.unwrap();
+        assert_eq!(run.items.len(), 1);
+        assert_eq!(run.items[0].price, 99.0);
+    }
+
+    #[tokio::test]
+    async fn follows_pagination_until_the_last_page() {
+        let config = Config {
+            retries: 0,
+            ..Config::default()
+        };
+        let tile = |name: &str, next: &str| {
+            format!(
+                r#"<li class="product-item"><div class="product-item__caption"><a href="/velas/{0}">{0}</a></div></li>{1}"#,
+                name, next
+            )
+        };
+        let velas = format!("{}velas", config.root_url);
+        let pages = StaticPages(
+            vec![
+                (
+                    velas.clone(),
+                    tile("uno", r#"<a rel="next" href="?page=2">›</a>"#),
+                ),
+                (
+                    format!("{}?page=2", velas),
+                    tile("dos", r#"<a href="/velas?page=1">1</a>"#),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        );
+
+        let result = Scraper::new(pages, config)
+            .scrape_category(&velas)
+            .await
+            .unwrap();
+        assert_eq!(result.products.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn searches_the_site_for_a_keyword() {
+        let config = Config {
+            retries: 0,
+            ..Config::default()
+        };
+        let pages = StaticPages(
+            vec![(
+                format!("{}catalogsearch/result/?q=pumpkin+pecan", config.root_url),
+                include_str!("../fixtures/category_listing.html").to_string(),
+            )]
+            .into_iter()
+            .collect(),
+        );
+
+        let run = Scraper::new(pages, config)
+            .scrape_search("pumpkin pecan")
+            .await
+            .unwrap();
+        assert_eq!(run.items.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn deep_mode_enriches_items_from_product_pages() {
+        let config = Config {
+            retries: 0,
+            deep: true,
+            ..Config::default()
+        };
+        let root = config.root_url.to_string();
+        let pages = StaticPages(
+            vec![
+                (
+                    root.clone(),
+                    r#"<nav><a href="/velas">Velas</a></nav>"#.to_string(),
+                ),
+                (
+                    format!("{}velas", root),
+                    include_str!("../fixtures/category_listing.html").to_string(),
+                ),
+                (
+                    format!("{}velas/vela-3-mechas-producto-uno", root),
+                    r#"<div class="product attribute sku"><div class="value">026</div></div>"#
+                        .to_string(),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        );
+
+        let run = Scraper::new(pages, config).scrape_all().await.unwrap();
+        let enriched: Vec<&str> = run
+            .items
+            .iter()
+            .filter_map(|item| item.details.as_ref())
+            .map(|details| details.sku.as_str())
+            .collect();
+        assert_eq!(enriched, vec!["026"]);
+    }
+
+    #[tokio::test]
+    async fn spent_budget_keeps_listings_but_skips_details() {
+        let config = Config {
+            retries: 0,
+            deep: true,
+            max_runtime: Some(0),
+            ..Config::default()
+        };
+        let root = config.root_url.to_string();
+        let pages = StaticPages(
+            vec![
+                (
+                    root.clone(),
+                    r#"<nav><a href="/velas">Velas</a></nav>"#.to_string(),
+                ),
+                (
+                    format!("{}velas", root),
+                    include_str!("../fixtures/category_listing.html").to_string(),
+                ),
+                (
+                    format!("{}velas/vela-3-mechas-producto-uno", root),
+                    r#"<div class="product attribute sku"><div class="value">026</div></div>"#
+                        .to_string(),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        );
+
+        let run = Scraper::new(pages, config).scrape_all().await.unwrap();
+        assert_eq!(run.items.len(), 3);
+        assert!(run.items.iter().all(|item| item.details.is_none()));
+    }
+
+    struct Flaky {
+        failures: u32,
+        calls: AtomicU32,
+    }
+
+    #[async_trait]
+    impl Fetcher for Flaky {
+        async fn fetch(&self, _url: &str) -> Result<String, Report> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+                Err(eyre!("connection reset"))
+            } else {
+                Ok(String::new())
+            }
+        }
+    }
+
+    /// Answers each status in turn, then the listing.
+    struct Answers {
+        statuses: Vec<StatusCode>,
+        calls: Arc<AtomicU32>,
+    }
+
+    #[async_trait]
+    impl Fetcher for Answers {
+        async fn fetch(&self, _url: &str) -> Result<String, Report> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst) as usize;
+            match self.statuses.get(call) {
+                Some(&status) => Err(HttpStatus(status).into()),
+                None => Ok(include_str!("../fixtures/category_listing.html").to_string()),
+            }
+        }
+    }
+
+    /// Serves a listing only from the second fetch on.
+    struct RendersLate(AtomicU32);
+
+    #[async_trait]
+    impl Fetcher for RendersLate {
+        async fn fetch(&self, _url: &str) -> Result<String, Report> {
+            if self.0.fetch_add(1, Ordering::SeqCst) == 0 {
+                Ok(r#"<p class="toolbar-amount"><span class="toolbar-number">3</span> artículos</p><ol class="products"></ol>"#.to_string())
+            } else {
+                Ok(include_str!("../fixtures/category_listing.html").to_string())
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn fetches_again_a_page_that_declares_products_but_lists_none() {
+        let config = Config {
+            retries: 0,
+            ignore_robots: true,
+            empty_retry_delay_ms: 1,
+            ..Config::default()
+        };
+
+        let result = Scraper::new(RendersLate(AtomicU32::new(0)), config)
+            .scrape_category("/velas")
+            .await
+            .unwrap();
+        assert_eq!(result.products.len(), 3);
+        assert_eq!(
+            result.empty_retry,
+            Some(EmptyRetry {
+                url: "/velas".to_string(),
+                declared: 3,
+                attempts: 1,
+                recovered: true,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn retries_failed_fetches_up_to_the_limit() {
+        let config = Config {
+            retries: 2,
+            ignore_robots: true,
+            backoff_ms: 1,
+            ..Config::default()
+        };
+        let flaky = |failures| Flaky {
+            failures,
+            calls: AtomicU32::new(0),
+        };
+
+        let recovers = Scraper::new(flaky(2), config.clone());
+        assert!(recovers.scrape_category("/velas").await.is_ok());
+
+        let gives_up = Scraper::new(flaky(3), config);
+        assert!(gives_up.scrape_category("/velas").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors_but_not_missing_pages() {
+        let config = Config {
+            retries: 2,
+            ignore_robots: true,
+            backoff_ms: 1,
+            ..Config::default()
+        };
+        let calls = Arc::new(AtomicU32::new(0));
+        let answers = |statuses| Answers {
+            statuses,
+            calls: calls.clone(),
+        };
+
+        let busy = Scraper::new(
+            answers(vec![StatusCode::SERVICE_UNAVAILABLE]),
+            config.clone(),
+        );
+        let result = busy.scrape_category("/velas").await.unwrap();
+        assert_eq!(result.products.len(), 3);
+        assert_eq!(calls.swap(0, Ordering::SeqCst), 2);
+
+        let missing = Scraper::new(answers(vec![StatusCode::NOT_FOUND]), config);
+        assert!(missing.scrape_category("/velas").await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,431 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+use color_eyre::eyre::eyre;
+use color_eyre::Report;
+use select::document::Document;
+use select::node::Node;
+use select::predicate::Any;
+use serde::Deserialize;
+
+/// One space-separated part of a selector, e.g. `li.product-item[data-sku]`.
+#[derive(Debug, Clone, PartialEq, Default)]
+struct Compound {
+    tag: Option<String>,
+    classes: Vec<String>,
+    attrs: Vec<(String, Option<String>)>,
+}
+
+impl Compound {
+    fn parse(text: &str) -> Result<Self, Report> {
+        let mut compound = Compound::default();
+        let is_ident = |c: char| c.is_alphanumeric() || c == '-' || c == '_';
+
+        let tag_end = text.find(|c: char| !is_ident(c)).unwrap_or(text.len());
+        if tag_end > 0 {
+            compound.tag = Some(text[..tag_end].to_ascii_lowercase());
+        }
+        let mut rest = &text[tag_end..];
+
+        while let Some(marker) = rest.chars().next() {
+            match marker {
+                '.' | '#' => {
+                    let end = rest[1..]
+                        .find(|c: char| !is_ident(c))
+                        .map_or(rest.len(), |i| i + 1);
+                    let name = rest[1..end].to_string();
+                    if name.is_empty() {
+                        return Err(eyre!(
+                            "Empty name after {:?} in selector {:?}",
+                            marker,
+                            text
+                        ));
+                    }
+                    if marker == '.' {
+                        compound.classes.push(name);
+                    } else {
+                        compound.attrs.push(("id".to_string(), Some(name)));
+                    }
+                    rest = &rest[end..];
+                }
+                '[' => {
+                    let end = rest
+                        .find(']')
+                        .ok_or_else(|| eyre!("Unclosed [ in selector {:?}", text))?;
+                    let attr = match rest[1..end].split_once('=') {
+                        Some((name, value)) => (
+                            name.trim().to_string(),
+                            Some(
+                                value
+                                    .trim()
+                                    .trim_matches(|c| c == '"' || c == '\'')
+                                    .to_string(),
+                            ),
+                        ),
+                        None => (rest[1..end].trim().to_string(), None),
+                    };
+                    compound.attrs.push(attr);
+                    rest = &rest[end + 1..];
+                }
+                _ => return Err(eyre!("Unsupported {:?} in selector {:?}", marker, text)),
+            }
+        }
+        Ok(compound)
+    }
+
+    fn matches(&self, node: Node) -> bool {
+        if node.name().is_none() {
+            return false;
+        }
+        if let Some(tag) = &self.tag {
+            if node.name() != Some(tag.as_str()) {
+                return false;
+            }
+        }
+        let classes = node.attr("class").unwrap_or_default();
+        self.classes
+            .iter()
+            .all(|class| classes.split_whitespace().any(|c| c == class))
+            && self.attrs.iter().all(|(name, value)| match value {
+                Some(value) => node.attr(name) == Some(value.as_str()),
+                None => node.attr(name).is_some(),
+            })
+    }
+}
+
+/// A CSS selector limited to what listings need: tags, `.class`, `#id`,
+/// `[attr]` and `[attr=value]`, joined by the descendant combinator.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Selector {
+    source: String,
+    steps: Vec<Compound>,
+}
+
+impl Selector {
+    pub fn parse(source: &str) -> Result<Self, Report> {
+        let steps = source
+            .split_whitespace()
+            .map(Compound::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        if steps.is_empty() {
+            return Err(eyre!("Empty selector"));
+        }
+        Ok(Selector {
+            source: source.to_string(),
+            steps,
+        })
+    }
+
+    /// Whether `node` matches, with the earlier steps matched by its ancestors
+    /// up to and including `scope`.
+    fn matches(&self, node: Node, scope: Option<Node>) -> bool {
+        let (last, earlier) = self.steps.split_last().expect("selectors have a step");
+        if !last.matches(node) {
+            return false;
+        }
+
+        let mut pending = earlier.iter().rev().peekable();
+        let mut ancestor = node.parent();
+        while let (Some(step), Some(current)) = (pending.peek(), ancestor) {
+            if step.matches(current) {
+                pending.next();
+            }
+            if Some(current.index()) == scope.map(|scope| scope.index()) {
+                break;
+            }
+            ancestor = current.parent();
+        }
+        pending.peek().is_none()
+    }
+
+    /// First descendant of `scope` that matches, in document order.
+    pub fn first<'a>(&self, scope: Node<'a>) -> Option<Node<'a>> {
+        scope
+            .descendants()
+            .find(|node| self.matches(*node, Some(scope)))
+    }
+
+    /// Every matching node of the document, in document order.
+    pub fn all<'a>(&self, document: &'a Document) -> Vec<Node<'a>> {
+        document
+            .find(Any)
+            .filter(|node| self.matches(*node, None))
+            .collect()
+    }
+}
+
+impl TryFrom<String> for Selector {
+    type Error = Report;
+
+    fn try_from(source: String) -> Result<Self, Report> {
+        Selector::parse(&source)
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+pub(crate) fn selector(source: &str) -> SelectorChain {
+    SelectorChain::from(Selector::parse(source).expect("built-in selector is valid"))
+}
+
+/// Entry of a chain that falls back to the page's schema.org JSON-LD.
+pub const JSON_LD: &str = "json-ld";
+
+/// Selectors for one field, tried in order until one matches, so the
+/// previous markup can stay listed after a redesign. Written as a single
+/// selector or a list; a `"json-ld"` entry falls back to the product data in
+/// the page's JSON-LD, matched by link, for fields it carries.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "ChainSource")]
+pub struct SelectorChain {
+    selectors: Vec<Selector>,
+    json_ld: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ChainSource {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl SelectorChain {
+    /// The value `read` gets from the first match inside `scope`, and the
+    /// rank of the selector that found it. A selector whose match `read`
+    /// turns down (empty, unparseable) falls through to the next one.
+    pub fn first<'a, T>(
+        &self,
+        scope: Node<'a>,
+        mut read: impl FnMut(Node<'a>) -> Option<T>,
+    ) -> Option<(usize, T)> {
+        self.selectors
+            .iter()
+            .enumerate()
+            .find_map(|(rank, selector)| {
+                selector
+                    .first(scope)
+                    .and_then(&mut read)
+                    .map(|value| (rank, value))
+            })
+    }
+
+    /// Nodes of the first selector that matches anything in the document,
+    /// and its rank.
+    pub fn all<'a>(&self, document: &'a Document) -> Option<(usize, Vec<Node<'a>>)> {
+        self.selectors
+            .iter()
+            .map(|selector| selector.all(document))
+            .enumerate()
+            .find(|(_, nodes)| !nodes.is_empty())
+    }
+
+    pub fn uses_json_ld(&self) -> bool {
+        self.json_ld
+    }
+
+    /// Rank of the JSON-LD fallback, after every selector.
+    pub fn json_ld_rank(&self) -> usize {
+        self.selectors.len()
+    }
+
+    /// How the entry at `rank` is written in a config file.
+    pub fn entry(&self, rank: usize) -> String {
+        match self.selectors.get(rank) {
+            Some(selector) => selector.to_string(),
+            None => JSON_LD.to_string(),
+        }
+    }
+
+    /// This chain with the JSON-LD fallback added at the end.
+    pub fn or_json_ld(self) -> Self {
+        SelectorChain {
+            json_ld: true,
+            ..self
+        }
+    }
+}
+
+impl From<Selector> for SelectorChain {
+    fn from(selector: Selector) -> Self {
+        SelectorChain {
+            selectors: vec![selector],
+            json_ld: false,
+        }
+    }
+}
+
+impl TryFrom<ChainSource> for SelectorChain {
+    type Error = Report;
+
+    fn try_from(source: ChainSource) -> Result<Self, Report> {
+        let entries = match source {
+            ChainSource::One(entry) => vec![entry],
+            ChainSource::Many(entries) => entries,
+        };
+        let mut chain = SelectorChain {
+            selectors: vec![],
+            json_ld: false,
+        };
+        for entry in entries {
+            match entry.trim() {
+                JSON_LD => chain.json_ld = true,
+                _ if chain.json_ld => {
+                    return Err(eyre!(
+                        "{:?} must be the last entry of a selector list",
+                        JSON_LD
+                    ))
+                }
+                _ => chain.selectors.push(Selector::parse(&entry)?),
+            }
+        }
+        if chain.selectors.is_empty() && !chain.json_ld {
+            return Err(eyre!("Empty selector list"));
+        }
+        Ok(chain)
+    }
+}
+
+impl fmt::Display for SelectorChain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let entries: Vec<String> = (0..self.selectors.len() + usize::from(self.json_ld))
+            .map(|rank| self.entry(rank))
+            .collect();
+        f.write_str(&entries.join(", "))
+    }
+}
+
+/// How often each entry of each field's chain matched, to spot fields that
+/// only still work through a fallback.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SelectorHits {
+    /// Field name to chain entry to match count.
+    pub fields: BTreeMap<String, BTreeMap<String, usize>>,
+    /// The same, for matches by anything but the field's first entry.
+    pub fallbacks: BTreeMap<String, BTreeMap<String, usize>>,
+}
+
+impl SelectorHits {
+    pub fn record(&mut self, field: &str, chain: &SelectorChain, rank: usize) {
+        let count = |counts: &mut BTreeMap<String, BTreeMap<String, usize>>| {
+            *counts
+                .entry(field.to_string())
+                .or_default()
+                .entry(chain.entry(rank))
+                .or_default() += 1;
+        };
+        count(&mut self.fields);
+        if rank > 0 {
+            count(&mut self.fallbacks);
+        }
+    }
+
+    pub fn absorb(&mut self, other: SelectorHits) {
+        for (mine, theirs) in [
+            (&mut self.fields, other.fields),
+            (&mut self.fallbacks, other.fallbacks),
+        ] {
+            for (field, entries) in theirs {
+                let counts = mine.entry(field).or_default();
+                for (entry, count) in entries {
+                    *counts.entry(entry).or_default() += count;
+                }
+            }
+        }
+    }
+}
+
+/// Where each field of a product tile lives. Defaults match the Mexican
+/// site's markup; a config file's `[selectors]` table can replace any of them.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SelectorProfile {
+    /// One product tile; the other selectors are matched inside it.
+    pub item: SelectorChain,
+    pub name: SelectorChain,
+    /// Its `href` is the product link.
+    pub link: SelectorChain,
+    pub item_type: SelectorChain,
+    pub price: SelectorChain,
+    pub promo: SelectorChain,
+    pub discount: SelectorChain,
+    /// Its `data-src` or `src` is the image URL.
+    pub image: SelectorChain,
+}
+
+impl Default for SelectorProfile {
+    fn default() -> Self {
+        SelectorProfile {
+            item: selector(".product-item"),
+            name: selector(".product-item__caption a").or_json_ld(),
+            link: selector(".product-item__caption a"),
+            item_type: selector(".product-item__form li"),
+            price: selector(".product-item__price span").or_json_ld(),
+            promo: selector(".product-item__price .price-new"),
+            discount: selector(".product-item__flags--discounts p"),
+            image: selector("img").or_json_ld(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_descendant_chains_inside_a_scope() {
+        let document = Document::from(
+            r#"<div class="page"><li class="product-item" data-sku="1">
+                 <div class="price"><span class="old">$2</span><span class="price-new">$1</span></div>
+               </li></div>"#,
+        );
+        let tile = Selector::parse("li.product-item[data-sku=1]")
+            .unwrap()
+            .all(&document)[0];
+
+        let promo = Selector::parse("div.price .price-new").unwrap();
+        assert_eq!(
+            promo.first(tile).map(|node| node.text()),
+            Some("$1".to_string())
+        );
+        assert!(Selector::parse(".page .old").unwrap().first(tile).is_none());
+        assert!(Selector::parse("div > span").is_err());
+    }
+
+    #[test]
+    fn chains_fall_back_in_order() {
+        #[derive(Deserialize)]
+        struct Fields {
+            price: SelectorChain,
+        }
+        let fields: Fields =
+            toml::from_str(r#"price = [".price-new", ".price .old", "json-ld"]"#).unwrap();
+        let chain = fields.price;
+        assert_eq!(chain.to_string(), ".price-new, .price .old, json-ld");
+        assert!(toml::from_str::<Fields>(r#"price = ["json-ld", ".old"]"#).is_err());
+
+        let document = Document::from(
+            r#"<div class="price"><span class="price-new"> </span><span class="old">$2</span></div>"#,
+        );
+        let page = document.nth(0).unwrap();
+        let text = |node: Node| Some(node.text()).filter(|text| !text.trim().is_empty());
+        let (rank, text) = chain.first(page, text).unwrap();
+        assert_eq!((rank, text.as_str()), (1, "$2"));
+        assert_eq!(chain.first(page, |_| None::<()>), None);
+
+        let mut hits = SelectorHits::default();
+        hits.record("price", &chain, rank);
+        hits.record("price", &chain, 0);
+        hits.record("price", &chain, chain.json_ld_rank());
+        assert_eq!(hits.fields["price"].len(), 3);
+        assert_eq!(
+            hits.fallbacks["price"].keys().collect::<Vec<_>>(),
+            [".price .old", "json-ld"]
+        );
+    }
+}
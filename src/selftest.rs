@@ -0,0 +1,73 @@
+use color_eyre::eyre::eyre;
+use color_eyre::Report;
+use serde_json::Value;
+
+use crate::config::Config;
+use crate::parse_products;
+use crate::selector::SelectorHits;
+
+/// Anonymized pages bundled into the binary, with the items they must yield.
+const GOLDEN: &[(&str, &str, &str)] = &[
+    (
+        "category_listing",
+        include_str!("../fixtures/category_listing.html"),
+        include_str!("../fixtures/category_listing.expected.json"),
+    ),
+    (
+        "malformed_listing",
+        include_str!("../fixtures/malformed_listing.html"),
+        include_str!("../fixtures/malformed_listing.expected.json"),
+    ),
+];
+
+/// Runs extraction over every golden page and describes each mismatch.
+pub fn check() -> Vec<String> {
+    let config = Config::default();
+    let mut failures = vec![];
+
+    for (name, html, expected) in GOLDEN {
+        let expected: Value = match serde_json::from_str(expected) {
+            Ok(expected) => expected,
+            Err(err) => {
+                failures.push(format!("{}: unreadable expectation: {}", name, err));
+                continue;
+            }
+        };
+        let actual =
+            serde_json::to_value(parse_products(html, &config, &mut SelectorHits::default()))
+                .unwrap_or_default();
+
+        if actual != expected {
+            failures.push(format!("{}: expected {}, got {}", name, expected, actual));
+        }
+    }
+
+    failures
+}
+
+pub fn run() -> Result<(), Report> {
+    let failures = check();
+    for failure in &failures {
+        println!("FAIL {}", failure);
+    }
+
+    if failures.is_empty() {
+        println!("selftest: {} golden pages OK", GOLDEN.len());
+        Ok(())
+    } else {
+        Err(eyre!(
+            "selftest: {} of {} golden pages failed",
+            failures.len(),
+            GOLDEN.len()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn golden_pages_extract_as_expected() {
+        let failures = super::check();
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+}
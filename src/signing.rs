@@ -0,0 +1,112 @@
+#[cfg(feature = "signing")]
+use std::convert::TryInto;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::eyre;
+use color_eyre::Report;
+#[cfg(feature = "signing")]
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use tracing::info;
+
+use crate::manifest::{sha256_file, Artifact, Manifest};
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(feature = "signing")]
+fn hex_bytes<const N: usize>(text: &str, what: &str) -> Result<[u8; N], Report> {
+    let bytes = hex::decode(text.trim()).map_err(|e| eyre!("Invalid {}: {}", what, e))?;
+    bytes
+        .try_into()
+        .map_err(|_| eyre!("Invalid {}: expected {} hex-encoded bytes", what, N))
+}
+
+/// Writes `<path>.sha256` in `sha256sum` format, so `sha256sum -c` can check it.
+pub fn write_checksum(path: &Path) -> Result<(), Report> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    fs::write(
+        with_suffix(path, ".sha256"),
+        format!("{}  {}\n", sha256_file(path)?, name),
+    )?;
+    Ok(())
+}
+
+#[cfg(feature = "signing")]
+/// Reads an ed25519 secret key stored as 64 hex characters (its 32-byte seed).
+pub fn load_signing_key(path: &Path) -> Result<SigningKey, Report> {
+    let seed = hex_bytes::<32>(&fs::read_to_string(path)?, "signing key")?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+#[cfg(feature = "signing")]
+/// Signs a file's contents into `<path>.sig` as a hex ed25519 signature and
+/// returns the hex public key consumers need to check it.
+pub fn sign_file(path: &Path, key: &SigningKey) -> Result<String, Report> {
+    let signature = key.sign(&fs::read(path)?);
+    fs::write(with_suffix(path, ".sig"), hex::encode(signature.to_bytes()))?;
+    Ok(hex::encode(key.verifying_key().to_bytes()))
+}
+
+#[cfg(feature = "signing")]
+pub fn verify_file(path: &Path, public_key: &str) -> Result<(), Report> {
+    let key = VerifyingKey::from_bytes(&hex_bytes::<32>(public_key, "public key")?)?;
+    let signature = hex_bytes::<64>(&fs::read_to_string(with_suffix(path, ".sig"))?, "signature")?;
+    key.verify(&fs::read(path)?, &Signature::from_bytes(&signature))
+        .map_err(|_| eyre!("Signature of {:?} does not match", path))
+}
+
+/// Checks the manifest's signature when a public key is given, then the size
+/// and hash of every artifact it lists.
+pub fn verify_manifest(path: &Path, public_key: Option<&str>) -> Result<(), Report> {
+    match public_key {
+        #[cfg(feature = "signing")]
+        Some(public_key) => verify_file(path, public_key)?,
+        #[cfg(not(feature = "signing"))]
+        Some(_) => {
+            return Err(eyre!(
+                "--public-key needs bnbscraper built with the signing feature"
+            ))
+        }
+        None => {}
+    }
+
+    let manifest: Manifest = serde_json::from_slice(&fs::read(path)?)?;
+    for listed in &manifest.artifacts {
+        let actual = Artifact::describe(&listed.path, &listed.kind)?;
+        if actual != *listed {
+            return Err(eyre!(
+                "{:?} changed since the manifest was written",
+                listed.path
+            ));
+        }
+    }
+
+    info!("{} artifacts match {:?}", manifest.artifacts.len(), path);
+    Ok(())
+}
+
+#[cfg(all(test, feature = "signing"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signatures_verify_only_for_unchanged_files() {
+        let path = std::env::temp_dir().join(format!("bnbscraper-{}.signed", std::process::id()));
+        fs::write(&path, "[]").unwrap();
+        let key = SigningKey::from_bytes(&[7; 32]);
+
+        let public_key = sign_file(&path, &key).unwrap();
+        let untouched = verify_file(&path, &public_key);
+        fs::write(&path, "[{}]").unwrap();
+        let tampered = verify_file(&path, &public_key);
+        fs::remove_file(with_suffix(&path, ".sig")).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(untouched.is_ok());
+        assert!(tampered.is_err());
+    }
+}
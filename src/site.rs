@@ -0,0 +1,148 @@
+use clap::ValueEnum;
+use reqwest::Url;
+use serde::Deserialize;
+
+use crate::discovery::normalize_link;
+use crate::selector::{selector, SelectorProfile};
+use crate::BnBItem;
+
+/// Whether a site's listed prices include its sales tax, and the rate to
+/// add when they don't, so `price_with_tax` compares across sites.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TaxSettings {
+    pub prices_exclude_tax: bool,
+    pub rate: f32,
+}
+
+/// A Bath & Body Works storefront the scraper knows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Site {
+    /// bathandbodyworks.mx
+    Mx,
+    /// bathandbodyworks.com
+    Us,
+    /// bathandbodyworks.ca
+    Ca,
+}
+
+impl Site {
+    /// The site whose [`Site::code`] is `code`.
+    pub fn from_code(code: &str) -> Option<Site> {
+        Site::value_variants()
+            .iter()
+            .copied()
+            .find(|site| site.code() == code)
+    }
+
+    /// Short name items are tagged with.
+    pub fn code(self) -> &'static str {
+        match self {
+            Site::Mx => "mx",
+            Site::Us => "us",
+            Site::Ca => "ca",
+        }
+    }
+
+    pub fn root_url(self) -> Url {
+        let root = match self {
+            Site::Mx => "https://www.bathandbodyworks.mx",
+            Site::Us => "https://www.bathandbodyworks.com",
+            Site::Ca => "https://www.bathandbodyworks.ca",
+        };
+        Url::parse(root).expect("built-in site URL is valid")
+    }
+
+    /// The Mexican store lists prices with IVA. The US and Canadian stores
+    /// list them before sales tax, which varies by state and province: the
+    /// US default adds nothing and the Canadian one only the federal 5% GST,
+    /// until a `[site_tax.<site>]` table sets the local rate.
+    pub fn tax(self) -> TaxSettings {
+        match self {
+            Site::Mx => TaxSettings {
+                prices_exclude_tax: false,
+                rate: 0.16,
+            },
+            Site::Us => TaxSettings {
+                prices_exclude_tax: true,
+                rate: 0.0,
+            },
+            Site::Ca => TaxSettings {
+                prices_exclude_tax: true,
+                rate: 0.05,
+            },
+        }
+    }
+
+    /// Where product fields live on the site's listing pages. The US and
+    /// Canadian stores share one storefront platform and its tile markup.
+    pub fn selectors(self) -> SelectorProfile {
+        match self {
+            Site::Mx => SelectorProfile::default(),
+            Site::Us | Site::Ca => SelectorProfile {
+                item: selector(".product-tile"),
+                name: selector(".product-name a"),
+                link: selector(".product-name a"),
+                item_type: selector(".product-type"),
+                price: selector(".price .standard-price"),
+                promo: selector(".price .sale-price"),
+                discount: selector(".promo-message"),
+                image: selector("img"),
+            },
+        }
+    }
+
+    /// Absolute form of a product link on this site. The US and Canadian
+    /// stores append tracking parameters to every tile link, so their query
+    /// strings are dropped.
+    pub fn normalize_link(self, link: &str) -> String {
+        let absolute = match normalize_link(&self.root_url(), link) {
+            Some(absolute) => absolute,
+            None => return link.to_string(),
+        };
+        match self {
+            Site::Mx => absolute,
+            Site::Us | Site::Ca => absolute.split('?').next().unwrap_or_default().to_string(),
+        }
+    }
+
+    /// Marks `item` as coming from this site and makes its links absolute,
+    /// so items from several sites can share one output.
+    pub fn tag(self, item: &mut BnBItem) {
+        item.site = self.code().to_string();
+        item.link = self.normalize_link(&item.link);
+        if let Ok(image) = self.root_url().join(&item.image_url) {
+            if !item.image_url.is_empty() {
+                item.image_url = image.into();
+            }
+        }
+    }
+}
+
+/// Root URL of the storefront an item tagged `code` came from, or `fallback`
+/// (the `--root-url`) for untagged items.
+pub fn root_for(code: &str, fallback: &Url) -> Url {
+    Site::from_code(code).map_or_else(|| fallback.clone(), Site::root_url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_links_per_site() {
+        assert_eq!(
+            Site::Us.normalize_link("/p/mahogany-teakwood-3-wick-candle.html?cgid=candles#tile"),
+            "https://www.bathandbodyworks.com/p/mahogany-teakwood-3-wick-candle.html"
+        );
+        assert_eq!(
+            Site::Mx.normalize_link("/velas/vela-uno?color=rojo"),
+            "https://www.bathandbodyworks.mx/velas/vela-uno?color=rojo"
+        );
+        assert_eq!(
+            Site::Ca.normalize_link("https://elsewhere.example/uno"),
+            "https://elsewhere.example/uno"
+        );
+    }
+}
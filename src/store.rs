@@ -0,0 +1,552 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use color_eyre::eyre::eyre;
+use color_eyre::Report;
+use reqwest::Url;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::discovery::canonical_link;
+use crate::filters::effective_price;
+use crate::{unix_timestamp, BnBItem};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS products (
+    id INTEGER PRIMARY KEY,
+    link TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    item_type TEXT NOT NULL,
+    first_seen INTEGER NOT NULL,
+    last_seen INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    started_at INTEGER NOT NULL,
+    item_count INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS price_history (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    product_id INTEGER NOT NULL REFERENCES products(id),
+    scraped_at INTEGER NOT NULL,
+    price REAL NOT NULL,
+    price_promo REAL NOT NULL,
+    discount TEXT NOT NULL,
+    PRIMARY KEY (run_id, product_id)
+);
+CREATE TABLE IF NOT EXISTS review_queue (
+    id INTEGER PRIMARY KEY,
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    product_id INTEGER NOT NULL REFERENCES products(id),
+    scraped_at INTEGER NOT NULL,
+    price REAL NOT NULL,
+    price_promo REAL NOT NULL,
+    discount TEXT NOT NULL,
+    previous_price REAL NOT NULL
+);
+CREATE TABLE IF NOT EXISTS link_checks (
+    product_id INTEGER PRIMARY KEY REFERENCES products(id),
+    checked_at INTEGER NOT NULL,
+    status TEXT NOT NULL,
+    http_status INTEGER,
+    redirect_to TEXT
+);
+";
+
+// Bumped whenever SCHEMA changes in a way `migrate` has to handle.
+const SCHEMA_VERSION: i64 = 1;
+
+/// A price held back from the history because it moved by more than the
+/// review factor since the product's last recorded price.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingReview {
+    pub id: i64,
+    pub run_id: i64,
+    pub name: String,
+    pub link: String,
+    pub previous_price: f32,
+    pub price: f32,
+    pub price_promo: f32,
+}
+
+/// Price history kept in SQLite: one row per product, keyed by its
+/// canonical link, and one price row per product per run.
+pub struct SqliteStore {
+    conn: Connection,
+    review_factor: Option<f32>,
+}
+
+impl SqliteStore {
+    /// Opens a store from a `sqlite://path.db` URL, creating the schema if needed.
+    pub fn open(url: &str) -> Result<Self, Report> {
+        let path = url
+            .strip_prefix("sqlite://")
+            .ok_or_else(|| eyre!("Unsupported store URL {}, expected sqlite://path.db", url))?;
+        SqliteStore::open_path(Path::new(path))
+    }
+
+    pub fn open_path(path: &Path) -> Result<Self, Report> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(SqliteStore {
+            conn,
+            review_factor: None,
+        })
+    }
+
+    /// Queues prices that moved by `factor` or more (either way) for review
+    /// instead of recording them; see [`Self::resolve_review`].
+    pub fn with_review_factor(self, factor: Option<f32>) -> Self {
+        SqliteStore {
+            review_factor: factor,
+            ..self
+        }
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// Upserts every item with a link and appends its prices for this run,
+    /// or queues them for review when they look like a scrape glitch.
+    /// Returns the new run id.
+    pub fn record_run(&mut self, items: &[BnBItem], root: &Url) -> Result<i64, Report> {
+        let now = unix_timestamp() as i64;
+        let tx = self.conn.transaction()?;
+
+        tx.execute(
+            "INSERT INTO runs (started_at, item_count) VALUES (?1, ?2)",
+            params![now, items.len() as i64],
+        )?;
+        let run_id = tx.last_insert_rowid();
+
+        for item in items.iter().filter(|item| !item.link.is_empty()) {
+            let link = canonical_link(root, &item.link);
+            let product_id: i64 = tx.query_row(
+                "INSERT INTO products (link, name, item_type, first_seen, last_seen)
+                 VALUES (?1, ?2, ?3, ?4, ?4)
+                 ON CONFLICT(link) DO UPDATE SET
+                     name = excluded.name,
+                     item_type = excluded.item_type,
+                     last_seen = excluded.last_seen
+                 RETURNING id",
+                params![link, item.name, item.item_type, now],
+                |row| row.get(0),
+            )?;
+
+            let price = effective_price(item);
+            if let Some(factor) = self.review_factor {
+                let previous: Option<f32> = tx
+                    .query_row(
+                        "SELECT CASE WHEN price_promo > 0 THEN price_promo ELSE price END
+                         FROM price_history WHERE product_id = ?1
+                         ORDER BY run_id DESC LIMIT 1",
+                        params![product_id],
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+                if let Some(previous) =
+                    previous.filter(|previous| is_outlier(*previous, price, factor))
+                {
+                    tx.execute(
+                        "INSERT INTO review_queue
+                             (run_id, product_id, scraped_at, price, price_promo, discount, previous_price)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                        params![
+                            run_id,
+                            product_id,
+                            now,
+                            item.price,
+                            item.price_promo,
+                            item.discount,
+                            previous
+                        ],
+                    )?;
+                    continue;
+                }
+            }
+            tx.execute(
+                "INSERT OR REPLACE INTO price_history
+                     (run_id, product_id, scraped_at, price, price_promo, discount)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    run_id,
+                    product_id,
+                    now,
+                    item.price,
+                    item.price_promo,
+                    item.discount
+                ],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(run_id)
+    }
+
+    /// How many of run `run_id`'s prices were queued for review.
+    pub fn held_back(&self, run_id: i64) -> Result<usize, Report> {
+        Ok(self
+            .pending_reviews()?
+            .iter()
+            .filter(|review| review.run_id == run_id)
+            .count())
+    }
+
+    /// Queued prices, oldest first.
+    pub fn pending_reviews(&self) -> Result<Vec<PendingReview>, Report> {
+        let mut statement = self.conn.prepare(
+            "SELECT q.id, q.run_id, p.name, p.link, q.previous_price, q.price, q.price_promo
+             FROM review_queue q JOIN products p ON p.id = q.product_id
+             ORDER BY q.id",
+        )?;
+        let reviews = statement
+            .query_map([], |row| {
+                Ok(PendingReview {
+                    id: row.get(0)?,
+                    run_id: row.get(1)?,
+                    name: row.get(2)?,
+                    link: row.get(3)?,
+                    previous_price: row.get(4)?,
+                    price: row.get(5)?,
+                    price_promo: row.get(6)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(reviews)
+    }
+
+    /// Takes a price off the review queue, recording it in the history of
+    /// the run that scraped it when `accept` is set. Returns false for an
+    /// unknown id.
+    pub fn resolve_review(&mut self, id: i64, accept: bool) -> Result<bool, Report> {
+        let tx = self.conn.transaction()?;
+        if accept {
+            tx.execute(
+                "INSERT OR REPLACE INTO price_history
+                     (run_id, product_id, scraped_at, price, price_promo, discount)
+                 SELECT run_id, product_id, scraped_at, price, price_promo, discount
+                 FROM review_queue WHERE id = ?1",
+                params![id],
+            )?;
+        }
+        let removed = tx.execute("DELETE FROM review_queue WHERE id = ?1", params![id])?;
+        tx.commit()?;
+        Ok(removed > 0)
+    }
+
+    /// Up to `limit` product links, the ones never checked or checked longest
+    /// ago first.
+    pub fn links_to_verify(&self, limit: usize) -> Result<Vec<(i64, String)>, Report> {
+        let mut statement = self.conn.prepare(
+            "SELECT p.id, p.link FROM products p
+             LEFT JOIN link_checks c ON c.product_id = p.id
+             ORDER BY c.checked_at IS NOT NULL, c.checked_at, random()
+             LIMIT ?1",
+        )?;
+        let links = statement
+            .query_map(params![limit as i64], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+        Ok(links)
+    }
+
+    /// Replaces the last known link status of a product.
+    pub fn record_link_check(
+        &self,
+        product_id: i64,
+        status: &str,
+        http_status: Option<u16>,
+        redirect_to: Option<&str>,
+    ) -> Result<(), Report> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO link_checks
+                 (product_id, checked_at, status, http_status, redirect_to)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                product_id,
+                unix_timestamp() as i64,
+                status,
+                http_status,
+                redirect_to
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Brings an older store up to the current schema. Returns the version
+    /// it was at before.
+    pub fn migrate(&self) -> Result<i64, Report> {
+        let version: i64 = self
+            .conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(eyre!(
+                "Store schema version {} is newer than this build supports ({})",
+                version,
+                SCHEMA_VERSION
+            ));
+        }
+        // SCHEMA already created anything missing when the store was opened.
+        self.conn
+            .execute_batch(&format!("PRAGMA user_version = {}", SCHEMA_VERSION))?;
+        Ok(version)
+    }
+
+    /// Merges product rows whose links only differ in ways `canonical_link`
+    /// ignores, e.g. rows recorded before links were canonicalized. The
+    /// oldest row keeps the price history of all of them and takes the
+    /// canonical link, so later runs record onto it. Returns the number of
+    /// rows merged away.
+    pub fn dedupe_products(&mut self, root: &Url) -> Result<usize, Report> {
+        let tx = self.conn.transaction()?;
+        let products: Vec<(i64, String)> = {
+            let mut statement = tx.prepare("SELECT id, link FROM products ORDER BY id")?;
+            let rows = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<Result<_, _>>()?
+        };
+
+        let mut kept: HashMap<String, i64> = HashMap::new();
+        let mut merged = 0;
+        for (id, link) in products {
+            let key = canonical_link(root, &link);
+            match kept.get(&key) {
+                Some(&keep) => {
+                    tx.execute(
+                        "UPDATE OR IGNORE price_history SET product_id = ?1 WHERE product_id = ?2",
+                        params![keep, id],
+                    )?;
+                    tx.execute(
+                        "DELETE FROM price_history WHERE product_id = ?1",
+                        params![id],
+                    )?;
+                    tx.execute("DELETE FROM link_checks WHERE product_id = ?1", params![id])?;
+                    tx.execute(
+                        "UPDATE review_queue SET product_id = ?1 WHERE product_id = ?2",
+                        params![keep, id],
+                    )?;
+                    tx.execute(
+                        "UPDATE products SET
+                             first_seen = MIN(first_seen, (SELECT first_seen FROM products WHERE id = ?2)),
+                             last_seen = MAX(last_seen, (SELECT last_seen FROM products WHERE id = ?2))
+                         WHERE id = ?1",
+                        params![keep, id],
+                    )?;
+                    tx.execute("DELETE FROM products WHERE id = ?1", params![id])?;
+                    merged += 1;
+                }
+                None => {
+                    kept.insert(key, id);
+                }
+            }
+        }
+        // After the merges, so no other row holds the canonical link.
+        for (link, id) in &kept {
+            tx.execute(
+                "UPDATE products SET link = ?1 WHERE id = ?2 AND link != ?1",
+                params![link, id],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(merged)
+    }
+
+    /// Writes a consistent copy of the store to `path`, which must not exist.
+    pub fn backup(&self, path: &Path) -> Result<(), Report> {
+        self.conn
+            .execute("VACUUM INTO ?1", params![path.to_string_lossy()])?;
+        Ok(())
+    }
+
+    /// Items recorded in a past run: `runs_back` 0 is the latest run, 1 the one
+    /// before it. Returns no items if the store has fewer runs than that.
+    /// Prices still waiting for review count at their scraped value, so a
+    /// held product isn't mistaken for a removed one.
+    pub fn run_items(&self, runs_back: usize) -> Result<Vec<BnBItem>, Report> {
+        let mut statement = self.conn.prepare(
+            "WITH run AS (SELECT id FROM runs ORDER BY id DESC LIMIT 1 OFFSET ?1),
+                  prices AS (
+                      SELECT product_id, price, price_promo, discount FROM price_history
+                      WHERE run_id = (SELECT id FROM run)
+                      UNION ALL
+                      SELECT product_id, price, price_promo, discount FROM review_queue
+                      WHERE run_id = (SELECT id FROM run)
+                  )
+             SELECT p.name, p.item_type, p.link, h.price, h.price_promo, h.discount
+             FROM prices h JOIN products p ON p.id = h.product_id
+             ORDER BY p.link",
+        )?;
+        let items = statement
+            .query_map(params![runs_back as i64], |row| {
+                Ok(BnBItem {
+                    name: row.get(0)?,
+                    item_type: row.get(1)?,
+                    link: row.get(2)?,
+                    price: row.get(3)?,
+                    price_promo: row.get(4)?,
+                    discount: row.get(5)?,
+                    ..BnBItem::default()
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        Ok(items)
+    }
+}
+
+/// Whether a move from `previous` to `price` is at least `factor` times
+/// either way. A price dropping to or rising from zero always is.
+fn is_outlier(previous: f32, price: f32, factor: f32) -> bool {
+    if previous <= 0.0 || price <= 0.0 {
+        return previous != price;
+    }
+    price / previous >= factor || previous / price >= factor
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(link: &str, price_promo: f32) -> BnBItem {
+        BnBItem {
+            name: "Producto Uno".to_string(),
+            item_type: "Vela de 3 mechas".to_string(),
+            link: link.to_string(),
+            price: 650.0,
+            price_promo,
+            ..BnBItem::default()
+        }
+    }
+
+    #[test]
+    fn upserts_products_and_appends_price_history() {
+        let root = Url::parse("https://www.bathandbodyworks.mx").unwrap();
+        let mut store = SqliteStore::open_path(Path::new(":memory:")).unwrap();
+
+        store
+            .record_run(&[item("/velas/uno", 455.0)], &root)
+            .unwrap();
+        store
+            .record_run(
+                &[item("/velas/uno?color=rojo#top", 400.0), item("", 1.0)],
+                &root,
+            )
+            .unwrap();
+
+        let conn = store.connection();
+        let products: i64 = conn
+            .query_row("SELECT count(*) FROM products", [], |row| row.get(0))
+            .unwrap();
+        let promos: Vec<f64> = conn
+            .prepare("SELECT price_promo FROM price_history ORDER BY run_id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        assert_eq!(products, 1);
+        assert_eq!(promos, vec![455.0, 400.0]);
+        assert_eq!(store.run_items(1).unwrap()[0].price_promo, 455.0);
+        assert!(store.run_items(2).unwrap().is_empty());
+
+        let (product_id, _) = store.links_to_verify(10).unwrap()[0].clone();
+        store
+            .record_link_check(product_id, "dead", Some(404), None)
+            .unwrap();
+        assert_eq!(store.links_to_verify(10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn merges_products_recorded_under_equivalent_links() {
+        let root = Url::parse("https://www.bathandbodyworks.mx").unwrap();
+        let mut store = SqliteStore::open_path(Path::new(":memory:")).unwrap();
+        store
+            .record_run(&[item("/velas/uno", 455.0)], &root)
+            .unwrap();
+        // A row from before links lost their trailing slash.
+        store
+            .connection()
+            .execute("UPDATE products SET link = link || '/'", [])
+            .unwrap();
+        store
+            .record_run(&[item("/velas/uno", 400.0)], &root)
+            .unwrap();
+
+        assert_eq!(store.migrate().unwrap(), 0);
+        assert_eq!(store.dedupe_products(&root).unwrap(), 1);
+        store
+            .record_run(&[item("/velas/uno/", 380.0)], &root)
+            .unwrap();
+        let count = |table: &str| -> i64 {
+            store
+                .connection()
+                .query_row(&format!("SELECT count(*) FROM {}", table), [], |row| {
+                    row.get(0)
+                })
+                .unwrap()
+        };
+        assert_eq!(count("products"), 1);
+        assert_eq!(count("price_history"), 3);
+        assert_eq!(store.dedupe_products(&root).unwrap(), 0);
+    }
+
+    #[test]
+    fn queues_outlier_prices_for_review() {
+        let root = Url::parse("https://www.bathandbodyworks.mx").unwrap();
+        let mut store = SqliteStore::open_path(Path::new(":memory:"))
+            .unwrap()
+            .with_review_factor(Some(5.0));
+        store
+            .record_run(&[item("/velas/uno", 455.0)], &root)
+            .unwrap();
+        store
+            .record_run(&[item("/velas/uno", 45.5)], &root)
+            .unwrap();
+        store.record_run(&[item("/velas/uno", 4.5)], &root).unwrap();
+        store
+            .record_run(&[item("/velas/uno", 400.0)], &root)
+            .unwrap();
+
+        let history = |store: &SqliteStore| -> i64 {
+            store
+                .connection()
+                .query_row("SELECT count(*) FROM price_history", [], |row| row.get(0))
+                .unwrap()
+        };
+        let pending = store.pending_reviews().unwrap();
+        assert_eq!(history(&store), 2);
+        assert_eq!(
+            pending
+                .iter()
+                .map(|review| review.price_promo)
+                .collect::<Vec<_>>(),
+            vec![45.5, 4.5]
+        );
+        assert_eq!(pending[0].previous_price, 455.0);
+
+        assert!(store.resolve_review(pending[0].id, true).unwrap());
+        assert!(store.resolve_review(pending[1].id, false).unwrap());
+        assert!(!store.resolve_review(pending[1].id, false).unwrap());
+        assert_eq!(history(&store), 3);
+        assert_eq!(store.run_items(2).unwrap()[0].price_promo, 45.5);
+        assert!(store.pending_reviews().unwrap().is_empty());
+    }
+
+    #[test]
+    fn held_prices_dont_read_as_removed_products() {
+        let root = Url::parse("https://www.bathandbodyworks.mx").unwrap();
+        let mut store = SqliteStore::open_path(Path::new(":memory:"))
+            .unwrap()
+            .with_review_factor(Some(5.0));
+        store
+            .record_run(&[item("/velas/uno", 455.0)], &root)
+            .unwrap();
+        store.record_run(&[item("/velas/uno", 4.5)], &root).unwrap();
+
+        let latest = store.run_items(0).unwrap();
+        let changes = crate::diff::diff(&store.run_items(1).unwrap(), &latest, &root);
+
+        assert_eq!(latest[0].price_promo, 4.5);
+        assert!(changes.removed.is_empty());
+        assert_eq!(changes.price_changes.len(), 1);
+    }
+}
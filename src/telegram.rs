@@ -0,0 +1,252 @@
+use std::time::Duration;
+
+use color_eyre::eyre::eyre;
+use color_eyre::Report;
+use fluent_bundle::FluentValue;
+use reqwest::{header, Client, StatusCode};
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::diff::{PriceChange, RunDiff};
+use crate::discovery::canonical_link;
+use crate::i18n::Localizer;
+use crate::site::root_for;
+
+// Telegram rejects longer messages.
+const MAX_MESSAGE_CHARS: usize = 4096;
+// Telegram asks bots to stay under one message per second per chat.
+const MESSAGE_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_ATTEMPTS: u32 = 3;
+
+/// A price change that went down, with how much it fell.
+#[derive(Debug)]
+pub struct PriceDrop<'a> {
+    pub change: &'a PriceChange,
+    pub old: f32,
+    pub new: f32,
+    pub percent: f32,
+}
+
+/// Price changes that lowered the effective price and, when limits are set,
+/// either went below `below` or fell by at least `min_percent`.
+pub fn price_drops(
+    diff: &RunDiff,
+    below: Option<f32>,
+    min_percent: Option<f32>,
+) -> Vec<PriceDrop<'_>> {
+    diff.price_changes
+        .iter()
+        .filter_map(|change| {
+            let old = change.old_effective();
+            let new = change.new_effective();
+            if old <= 0.0 || new >= old {
+                return None;
+            }
+            let percent = (1.0 - new / old) * 100.0;
+            let wanted = (below.is_none() && min_percent.is_none())
+                || below.is_some_and(|below| new < below)
+                || min_percent.is_some_and(|min| percent >= min);
+            Some(PriceDrop {
+                change,
+                old,
+                new,
+                percent,
+            })
+            .filter(|_| wanted)
+        })
+        .collect()
+}
+
+/// Splits the lines into as few messages as fit Telegram's length limit,
+/// starting each with `header`.
+pub fn batch(header: &str, lines: &[String]) -> Vec<String> {
+    let mut messages = vec![];
+    let mut message = header.to_string();
+    for line in lines {
+        if message.chars().count() + 1 + line.chars().count() > MAX_MESSAGE_CHARS
+            && message != header
+        {
+            messages.push(std::mem::replace(&mut message, header.to_string()));
+        }
+        message.push('\n');
+        message.push_str(line);
+    }
+    if message != header {
+        messages.push(message);
+    }
+    messages
+}
+
+/// The alert messages for the new categories and price drops in `diff`, as
+/// they would be sent.
+pub fn messages(config: &Config, diff: &RunDiff, localizer: &Localizer) -> Vec<String> {
+    let drops = price_drops(diff, config.alert_below, config.alert_drop_percent);
+    if drops.is_empty() && diff.new_categories.is_empty() {
+        return vec![];
+    }
+
+    let mut lines: Vec<String> = diff
+        .new_categories
+        .iter()
+        .map(|link| localizer.text("telegram-new-category", &[("link", link.as_str().into())]))
+        .collect();
+    lines.extend(drops.iter().map(|drop| {
+        localizer.text(
+            "telegram-drop",
+            &[
+                ("name", drop.change.name.as_str().into()),
+                ("old", format!("{:.2}", drop.old).into()),
+                ("new", format!("{:.2}", drop.new).into()),
+                ("percent", format!("{:.0}", drop.percent).into()),
+                (
+                    "link",
+                    canonical_link(
+                        &root_for(&drop.change.site, &config.root_url),
+                        &drop.change.link,
+                    )
+                    .into(),
+                ),
+            ],
+        )
+    }));
+    let header = if drops.is_empty() {
+        localizer.text(
+            "telegram-categories-header",
+            &[("count", FluentValue::from(diff.new_categories.len()))],
+        )
+    } else {
+        localizer.text(
+            "telegram-header",
+            &[("count", FluentValue::from(drops.len()))],
+        )
+    };
+    batch(&header, &lines)
+}
+
+/// Sends the new categories and price drops in `diff` to the configured
+/// Telegram chat, batched into as few messages as possible. Does nothing
+/// unless both `--telegram-bot-token` and `--telegram-chat-id` are set.
+pub async fn notify(
+    client: &Client,
+    config: &Config,
+    diff: &RunDiff,
+    localizer: &Localizer,
+) -> Result<(), Report> {
+    let (token, chat_id) = match (&config.telegram_bot_token, &config.telegram_chat_id) {
+        (Some(token), Some(chat_id)) => (token, chat_id),
+        _ => return Ok(()),
+    };
+    let messages = messages(config, diff, localizer);
+    if messages.is_empty() {
+        return Ok(());
+    }
+
+    let url = format!("https://api.telegram.org/bot{}/sendMessage", token);
+    for (index, text) in messages.iter().enumerate() {
+        if index > 0 {
+            tokio::time::sleep(MESSAGE_INTERVAL).await;
+        }
+        let body = json!({
+            "chat_id": chat_id,
+            "text": text,
+            "disable_web_page_preview": true,
+        });
+        send(client, &url, &body).await?;
+    }
+    info!("Sent {} alert messages to Telegram", messages.len());
+    Ok(())
+}
+
+/// Posts one message, waiting out Telegram's `retry_after` when it answers
+/// 429 Too Many Requests.
+async fn send(client: &Client, url: &str, body: &Value) -> Result<(), Report> {
+    for _ in 0..MAX_ATTEMPTS {
+        let response = client
+            .post(url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+            .await
+            .map_err(|err| eyre!("Sending to Telegram failed: {}", err.without_url()))?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+
+        let answer: Value = serde_json::from_str(&response.text().await?).unwrap_or_default();
+        if status != StatusCode::TOO_MANY_REQUESTS {
+            // The token is part of the URL, so only the API's answer is reported.
+            return Err(eyre!(
+                "Telegram rejected the message ({}): {}",
+                status,
+                answer["description"].as_str().unwrap_or_default()
+            ));
+        }
+        let retry_after = answer["parameters"]["retry_after"].as_u64().unwrap_or(1);
+        warn!("Telegram rate limit hit, retrying in {}s", retry_after);
+        tokio::time::sleep(Duration::from_secs(retry_after)).await;
+    }
+    Err(eyre!("Telegram kept rate limiting the message"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i18n::Lang;
+
+    fn change(name: &str, old_promo: f32, new_promo: f32) -> PriceChange {
+        PriceChange {
+            site: String::new(),
+            name: name.to_string(),
+            item_type: String::new(),
+            link: format!("/velas/{}", name),
+            old_price: 650.0,
+            new_price: 650.0,
+            old_price_promo: old_promo,
+            new_price_promo: new_promo,
+        }
+    }
+
+    #[test]
+    fn picks_drops_past_either_limit_and_batches_them() {
+        let diff = RunDiff {
+            price_changes: vec![
+                change("barata", 455.0, 300.0),
+                change("poco", 455.0, 440.0),
+                change("sube", 400.0, 455.0),
+                change("mitad", 0.0, 325.0),
+            ],
+            ..RunDiff::default()
+        };
+
+        let names = |drops: Vec<PriceDrop>| -> Vec<String> {
+            drops.iter().map(|drop| drop.change.name.clone()).collect()
+        };
+        assert_eq!(
+            names(price_drops(&diff, Some(350.0), None)),
+            vec!["barata", "mitad"]
+        );
+        assert_eq!(names(price_drops(&diff, None, Some(40.0))), vec!["mitad"]);
+        assert_eq!(price_drops(&diff, None, None).len(), 3);
+
+        let config = Config {
+            alert_below: Some(1.0),
+            ..Config::default()
+        };
+        let localizer = Localizer::new(Lang::EnUs);
+        assert!(messages(&config, &diff, &localizer).is_empty());
+        let with_category = RunDiff {
+            new_categories: vec!["https://www.bathandbodyworks.mx/halloween".to_string()],
+            ..RunDiff::default()
+        };
+        let alert = messages(&config, &with_category, &localizer);
+        assert_eq!(alert.len(), 1);
+        assert!(alert[0].ends_with("https://www.bathandbodyworks.mx/halloween"));
+
+        let lines = vec!["x".repeat(3000), "y".repeat(3000), "z".to_string()];
+        let messages = batch("Bajas", &lines);
+        assert_eq!(messages.len(), 2);
+        assert!(messages[1].starts_with("Bajas\n") && messages[1].ends_with("\nz"));
+    }
+}
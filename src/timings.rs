@@ -0,0 +1,82 @@
+use std::time::Duration;
+
+use fluent_bundle::FluentValue;
+use tracing::info;
+
+use crate::i18n::Localizer;
+
+const SLOWEST_SHOWN: usize = 10;
+
+#[derive(Debug, Default)]
+pub struct LinkTiming {
+    pub url: String,
+    pub fetch: Duration,
+    pub parse: Duration,
+}
+
+impl LinkTiming {
+    fn total(&self) -> Duration {
+        self.fetch + self.parse
+    }
+}
+
+/// Where the time of a run went: fetching and parsing per URL, plus time
+/// spent handing items to the buffer and writing outputs.
+#[derive(Debug, Default)]
+pub struct RunTimings {
+    links: Vec<LinkTiming>,
+    sink: Duration,
+}
+
+impl RunTimings {
+    pub fn record_link(&mut self, timing: LinkTiming) {
+        self.links.push(timing);
+    }
+
+    pub fn absorb(&mut self, other: RunTimings) {
+        self.links.extend(other.links);
+        self.sink += other.sink;
+    }
+
+    pub fn add_sink(&mut self, elapsed: Duration) {
+        self.sink += elapsed;
+    }
+
+    pub fn slowest(&self, count: usize) -> Vec<&LinkTiming> {
+        let mut links: Vec<&LinkTiming> = self.links.iter().collect();
+        links.sort_by_key(|timing| std::cmp::Reverse(timing.total()));
+        links.truncate(count);
+        links
+    }
+
+    pub fn report(&self, localizer: &Localizer) {
+        let fetch: Duration = self.links.iter().map(|timing| timing.fetch).sum();
+        let parse: Duration = self.links.iter().map(|timing| timing.parse).sum();
+
+        info!(
+            "{}",
+            localizer.text(
+                "timings-stages",
+                &[
+                    ("fetch", format!("{:.2?}", fetch).into()),
+                    ("parse", format!("{:.2?}", parse).into()),
+                    ("sink", format!("{:.2?}", self.sink).into()),
+                    ("pages", FluentValue::from(self.links.len())),
+                ],
+            )
+        );
+        for timing in self.slowest(SLOWEST_SHOWN) {
+            info!(
+                "{}",
+                localizer.text(
+                    "timings-slow-page",
+                    &[
+                        ("url", timing.url.as_str().into()),
+                        ("fetch", format!("{:.2?}", timing.fetch).into()),
+                        ("parse", format!("{:.2?}", timing.parse).into()),
+                    ],
+                )
+            );
+        }
+    }
+}
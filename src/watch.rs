@@ -0,0 +1,97 @@
+use std::fs;
+use std::path::Path;
+
+use color_eyre::eyre::WrapErr;
+use color_eyre::Report;
+use fluent_bundle::FluentValue;
+use reqwest::Url;
+use tracing::warn;
+
+use crate::discovery::normalize_link;
+use crate::filters::effective_price;
+use crate::i18n::Localizer;
+use crate::store::SqliteStore;
+use crate::Scraper;
+
+/// Product links from a watchlist file: one per line, relative to the site
+/// root or absolute, with blank lines and `#` comments ignored.
+pub fn parse_watchlist(text: &str, root: &Url) -> Vec<String> {
+    let mut links = vec![];
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match normalize_link(root, line) {
+            Some(link) if !links.contains(&link) => links.push(link),
+            Some(_) => {}
+            None => warn!("Skipping watchlist entry {:?}, it isn't on {}", line, root),
+        }
+    }
+    links
+}
+
+/// Scrapes the products listed in `watchlist`, records their prices as a run
+/// of the `history` store and prints each one.
+pub async fn watch(
+    scraper: &Scraper,
+    watchlist: &Path,
+    history: &str,
+    localizer: &Localizer,
+) -> Result<(), Report> {
+    let text = fs::read_to_string(watchlist)
+        .wrap_err_with(|| format!("Reading watchlist {:?}", watchlist))?;
+    let root = &scraper.config().root_url;
+    let links = parse_watchlist(&text, root);
+
+    let run = scraper.scrape_products(&links).await;
+    // Watched prices go through the same outlier review as --store.
+    let mut store = SqliteStore::open(history)?.with_review_factor(scraper.config().review_factor);
+    let run_id = store.record_run(&run.items, root)?;
+    let queued = store.held_back(run_id)?;
+    if queued > 0 {
+        warn!(
+            "Held back {} outlier prices from {}, see `bnbscraper --store {} review list`",
+            queued, history, history
+        );
+    }
+
+    for item in &run.items {
+        let status = if item.available {
+            "available"
+        } else {
+            "sold-out"
+        };
+        println!(
+            "{}",
+            localizer.text(
+                "watch-item",
+                &[
+                    ("name", item.name.as_str().into()),
+                    ("price", FluentValue::from(effective_price(item) as f64)),
+                    ("status", status.into()),
+                    ("link", item.link.as_str().into()),
+                ],
+            )
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_links_and_skips_comments_and_other_sites() {
+        let root = Url::parse("https://www.bathandbodyworks.mx").unwrap();
+        let text = "# Velas\n/velas/producto-uno\n\n  https://www.bathandbodyworks.mx/velas/producto-uno\nhttps://example.com/otro\n/jabones/dos\n";
+
+        assert_eq!(
+            parse_watchlist(text, &root),
+            vec![
+                "https://www.bathandbodyworks.mx/velas/producto-uno",
+                "https://www.bathandbodyworks.mx/jabones/dos",
+            ]
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::Path;
+
+use color_eyre::eyre::eyre;
+use color_eyre::Report;
+use reqwest::{Client, Method};
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use crate::diff::RunDiff;
+
+/// One outbound HTTP call, sent once per matching diff event. `url`, header
+/// values and `body` may reference `{{placeholders}}` filled from the event:
+/// event, site, name, item_type, link, price, price_promo, old_price and
+/// old_price_promo. A new category only fills event and link.
+#[derive(Deserialize, Debug, Clone)]
+pub struct WebhookTemplate {
+    #[serde(default = "default_method")]
+    pub method: String,
+    pub url: String,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub body: Option<String>,
+    /// Event kinds this template fires on: "added", "removed",
+    /// "price_change" and "new_category". Empty means every event.
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+fn default_method() -> String {
+    "POST".to_string()
+}
+
+/// A templates file is a JSON array of [`WebhookTemplate`]s.
+pub fn load_templates(path: &Path) -> Result<Vec<WebhookTemplate>, Report> {
+    Ok(serde_json::from_reader(File::open(path)?)?)
+}
+
+/// Placeholder values for each event in the diff, in added, removed,
+/// changed, new category order.
+pub fn events(diff: &RunDiff) -> Vec<BTreeMap<&'static str, String>> {
+    let mut events = vec![];
+    for (kind, items) in [("added", &diff.added), ("removed", &diff.removed)] {
+        for item in items {
+            events.push(BTreeMap::from([
+                ("event", kind.to_string()),
+                ("site", item.site.clone()),
+                ("name", item.name.clone()),
+                ("item_type", item.item_type.clone()),
+                ("link", item.link.clone()),
+                ("price", format!("{:.2}", item.price)),
+                ("price_promo", format!("{:.2}", item.price_promo)),
+            ]));
+        }
+    }
+    for change in &diff.price_changes {
+        events.push(BTreeMap::from([
+            ("event", "price_change".to_string()),
+            ("site", change.site.clone()),
+            ("name", change.name.clone()),
+            ("item_type", change.item_type.clone()),
+            ("link", change.link.clone()),
+            ("price", format!("{:.2}", change.new_price)),
+            ("price_promo", format!("{:.2}", change.new_price_promo)),
+            ("old_price", format!("{:.2}", change.old_price)),
+            ("old_price_promo", format!("{:.2}", change.old_price_promo)),
+        ]));
+    }
+    for link in &diff.new_categories {
+        events.push(BTreeMap::from([
+            ("event", "new_category".to_string()),
+            ("link", link.clone()),
+        ]));
+    }
+    events
+}
+
+/// Replaces every `{{key}}` with its value; unknown placeholders become empty.
+pub fn render(template: &str, values: &BTreeMap<&str, String>) -> String {
+    let mut rendered = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start..].find("}}") {
+            Some(end) => start + end,
+            None => break,
+        };
+        rendered.push_str(&rest[..start]);
+        let key = rest[start + 2..end].trim();
+        rendered.push_str(values.get(key).map(String::as_str).unwrap_or(""));
+        rest = &rest[end + 2..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+/// Sends every template for every matching event. A failed call is logged
+/// and does not stop the others.
+pub async fn push(
+    client: &Client,
+    templates: &[WebhookTemplate],
+    diff: &RunDiff,
+) -> Result<(), Report> {
+    let mut sent = 0;
+    for values in events(diff) {
+        for template in templates {
+            if !template.events.is_empty() && !template.events.contains(&values["event"]) {
+                continue;
+            }
+
+            let method = Method::from_bytes(template.method.to_uppercase().as_bytes())
+                .map_err(|_| eyre!("Invalid webhook method {}", template.method))?;
+            let mut request = client.request(method, render(&template.url, &values));
+            for (name, value) in &template.headers {
+                request = request.header(name, render(value, &values));
+            }
+            if let Some(body) = &template.body {
+                request = request.body(render(body, &values));
+            }
+
+            match request.send().await.and_then(|r| r.error_for_status()) {
+                Ok(_) => sent += 1,
+                Err(e) => warn!("Webhook {} failed: {}", template.url, e),
+            }
+        }
+    }
+    info!("Sent {} webhook calls", sent);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::PriceChange;
+
+    #[test]
+    fn renders_price_change_events_into_templates() {
+        let diff = RunDiff {
+            price_changes: vec![PriceChange {
+                site: String::new(),
+                name: "Producto Uno".to_string(),
+                item_type: "Vela".to_string(),
+                link: "/velas/uno".to_string(),
+                old_price: 650.0,
+                new_price: 650.0,
+                old_price_promo: 455.0,
+                new_price_promo: 400.0,
+            }],
+            new_categories: vec!["https://www.bathandbodyworks.mx/halloween".to_string()],
+            ..RunDiff::default()
+        };
+
+        let events = events(&diff);
+        let body = render(
+            r#"{"content": "{{ name }} {{old_price_promo}} -> {{price_promo}}{{missing}}"}"#,
+            &events[0],
+        );
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            render("{{event}} {{link}}", &events[1]),
+            "new_category https://www.bathandbodyworks.mx/halloween"
+        );
+        assert_eq!(body, r#"{"content": "Producto Uno 455.00 -> 400.00"}"#);
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::path::Path;
+
+use color_eyre::Report;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::output::write_json_atomically;
+use crate::unix_timestamp;
+
+// Keep roughly three months of daily runs.
+const MAX_RUNS: usize = 90;
+
+/// Items found per category link, plus every category link discovered,
+/// one entry per run, oldest first.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct YieldHistory {
+    runs: Vec<RunYield>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RunYield {
+    started_at: u64,
+    categories: BTreeMap<String, usize>,
+    #[serde(default)]
+    discovered: BTreeSet<String>,
+    /// Percentage of items with each field extracted.
+    #[serde(default)]
+    coverage: BTreeMap<String, f32>,
+}
+
+impl RunYield {
+    pub fn new(categories: BTreeMap<String, usize>, discovered: BTreeSet<String>) -> Self {
+        RunYield {
+            started_at: unix_timestamp(),
+            categories,
+            discovered,
+            coverage: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_coverage(self, coverage: BTreeMap<String, f32>) -> Self {
+        RunYield { coverage, ..self }
+    }
+}
+
+impl YieldHistory {
+    pub fn load(path: &Path) -> Result<Self, Report> {
+        if !path.exists() {
+            return Ok(YieldHistory::default());
+        }
+        Ok(serde_json::from_reader(File::open(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Report> {
+        write_json_atomically(path, self)
+    }
+
+    /// Categories whose yield fell by at least `threshold` (0.5 = -50%)
+    /// compared to the last recorded run, as `(link, previous, current)`.
+    pub fn drops(&self, current: &RunYield, threshold: f32) -> Vec<(String, usize, usize)> {
+        let previous = match self.runs.last() {
+            Some(previous) => previous,
+            None => return vec![],
+        };
+
+        current
+            .categories
+            .iter()
+            .filter_map(|(link, &count)| {
+                let &before = previous.categories.get(link)?;
+                let dropped = before > 0 && (count as f32) <= before as f32 * (1.0 - threshold);
+                if dropped {
+                    Some((link.clone(), before, count))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Category links discovered in `current` that no recorded run has seen.
+    /// Empty on the first run, when everything would otherwise be new.
+    pub fn new_categories(&self, current: &RunYield) -> Vec<String> {
+        if self.runs.is_empty() {
+            return vec![];
+        }
+
+        let seen: BTreeSet<&String> = self
+            .runs
+            .iter()
+            .flat_map(|run| run.discovered.iter().chain(run.categories.keys()))
+            .collect();
+        current
+            .discovered
+            .iter()
+            .filter(|link| !seen.contains(link))
+            .cloned()
+            .collect()
+    }
+
+    /// Coverage of each field over the recorded runs, oldest first. Runs
+    /// from before coverage was tracked are left out.
+    pub fn coverage_series(&self) -> BTreeMap<String, Vec<f32>> {
+        let mut series: BTreeMap<String, Vec<f32>> = BTreeMap::new();
+        for run in &self.runs {
+            for (field, percent) in &run.coverage {
+                series.entry(field.clone()).or_default().push(*percent);
+            }
+        }
+        series
+    }
+
+    /// Appends `current`, warning about yield drops, and returns the
+    /// categories it discovered that no earlier run had.
+    pub fn record(&mut self, current: RunYield, threshold: f32) -> Vec<String> {
+        let new_categories = self.new_categories(&current);
+        for link in &new_categories {
+            info!("New category discovered: {}", link);
+        }
+        for (link, before, count) in self.drops(&current, threshold) {
+            warn!(
+                "Yield for {} dropped from {} to {} items since the last run",
+                link, before, count
+            );
+        }
+
+        self.runs.push(current);
+        if self.runs.len() > MAX_RUNS {
+            let excess = self.runs.len() - MAX_RUNS;
+            self.runs.drain(..excess);
+        }
+        new_categories
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(counts: &[(&str, usize)]) -> RunYield {
+        RunYield::new(
+            counts
+                .iter()
+                .map(|&(link, count)| (link.to_string(), count))
+                .collect(),
+            counts.iter().map(|&(link, _)| link.to_string()).collect(),
+        )
+    }
+
+    #[test]
+    fn reports_categories_that_halved() {
+        let mut history = YieldHistory::default();
+        history.record(run(&[("/velas", 40), ("/jabones", 20), ("/nuevo", 0)]), 0.5);
+
+        let drops = history.drops(
+            &run(&[("/velas", 12), ("/jabones", 15), ("/nuevo", 0)]),
+            0.5,
+        );
+        assert_eq!(drops, vec![("/velas".to_string(), 40, 12)]);
+    }
+
+    #[test]
+    fn flags_categories_never_seen_before() {
+        let mut history = YieldHistory::default();
+        assert!(history.new_categories(&run(&[("/velas", 40)])).is_empty());
+
+        history.record(run(&[("/velas", 40)]), 0.5);
+        history.record(run(&[("/velas", 38), ("/jabones", 0)]), 0.5);
+
+        let current = run(&[("/velas", 41), ("/jabones", 10), ("/halloween", 25)]);
+        assert_eq!(history.new_categories(&current), vec!["/halloween"]);
+        assert_eq!(history.record(current, 0.5), vec!["/halloween"]);
+    }
+}